@@ -0,0 +1,61 @@
+//! Runs the engine on a signal that decays into silence, with every IIR filter enabled. Without
+//! denormal protection the filters' state would turn denormal towards the end and the `decay`
+//! benchmark would take several times longer than `steady`.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use rclip::engine::{ClipEngine, ClipEngineParams, Oversampling};
+use std::hint::black_box;
+
+const SAMPLE_RATE: f32 = 48000.0;
+const NUM_SAMPLES: usize = 48000;
+const BLOCK_SIZE: usize = 512;
+
+fn engine() -> ClipEngine {
+    let params = ClipEngineParams {
+        oversampling: Oversampling::X4,
+        pre_hpf_enable: true,
+        tone_enable: true,
+        dc_block: true,
+        ..ClipEngineParams::default()
+    };
+    ClipEngine::new(SAMPLE_RATE, 2, params)
+}
+
+fn run(engine: &mut ClipEngine, signal: &[f32]) {
+    let mut left = signal.to_vec();
+    let mut right = signal.to_vec();
+    for (left, right) in left
+        .chunks_mut(BLOCK_SIZE)
+        .zip(right.chunks_mut(BLOCK_SIZE))
+    {
+        engine.process_block(&mut [left, right]);
+    }
+    black_box((left, right));
+}
+
+fn denormals(c: &mut Criterion) {
+    // This falls by about 1500 dB over the second, reaching the denormal range halfway through
+    let decay: Vec<f32> = (0..NUM_SAMPLES)
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE;
+            (t * 440.0 * std::f32::consts::TAU).sin() * (-t * 170.0).exp()
+        })
+        .collect();
+    let steady: Vec<f32> = (0..NUM_SAMPLES)
+        .map(|i| (i as f32 / SAMPLE_RATE * 440.0 * std::f32::consts::TAU).sin() * 0.5)
+        .collect();
+
+    let mut group = c.benchmark_group("engine_denormals");
+    group.throughput(Throughput::Elements(NUM_SAMPLES as u64));
+
+    let mut decay_engine = engine();
+    group.bench_function("decay", |b| b.iter(|| run(&mut decay_engine, &decay)));
+
+    let mut steady_engine = engine();
+    group.bench_function("steady", |b| b.iter(|| run(&mut steady_engine, &steady)));
+
+    group.finish();
+}
+
+criterion_group!(benches, denormals);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+//! Runs a second of music-like material through the complete `ClipEngine` for a range of curves,
+//! oversampling factors, and block sizes, in mono and in stereo. The signal stays well above the
+//! threshold so the clip stage is busy the whole time.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use rclip::engine::{ClipCurve, ClipEngine, ClipEngineParams, Oversampling};
+use std::hint::black_box;
+
+const SAMPLE_RATE: f32 = 48000.0;
+const NUM_SAMPLES: usize = 48000;
+
+const CURVES: [(&str, ClipCurve); 4] = [
+    ("hard", ClipCurve::Hard),
+    ("hard_adaa", ClipCurve::HardAdaa),
+    ("tanh", ClipCurve::Tanh),
+    ("transparent", ClipCurve::Transparent),
+];
+const OVERSAMPLING: [(&str, Oversampling); 3] = [
+    ("1x", Oversampling::Off),
+    ("4x", Oversampling::X4),
+    ("8x", Oversampling::X8),
+];
+const BLOCK_SIZES: [usize; 3] = [32, 512, 4096];
+
+/// A few detuned partials with a pulsing envelope, peaking at around +6 dBFS. Every channel gets
+/// its own detuning so stereo pairs aren't identical.
+fn test_signal(channel: usize) -> Vec<f32> {
+    let detune = 1.0 + channel as f32 * 0.003;
+    (0..NUM_SAMPLES)
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE;
+            let phase = t * std::f32::consts::TAU * detune;
+            let envelope = 0.6 + 0.4 * (t * std::f32::consts::TAU * 2.0).sin();
+            let partials =
+                (phase * 55.0).sin() + 0.5 * (phase * 220.0).sin() + 0.25 * (phase * 1760.0).sin();
+            partials * envelope * 1.2
+        })
+        .collect()
+}
+
+fn run(engine: &mut ClipEngine, signal: &[Vec<f32>], block_size: usize) {
+    let mut channels = signal.to_vec();
+    for start in (0..NUM_SAMPLES).step_by(block_size) {
+        let end = (start + block_size).min(NUM_SAMPLES);
+        let mut blocks: Vec<&mut [f32]> = channels
+            .iter_mut()
+            .map(|channel| &mut channel[start..end])
+            .collect();
+        engine.process_block(&mut blocks);
+    }
+    black_box(channels);
+}
+
+fn engine(c: &mut Criterion) {
+    for num_channels in [1, 2] {
+        let signal: Vec<Vec<f32>> = (0..num_channels).map(test_signal).collect();
+        let layout = if num_channels == 1 { "mono" } else { "stereo" };
+
+        let mut group = c.benchmark_group(format!("engine_{layout}"));
+        group.throughput(Throughput::Elements(NUM_SAMPLES as u64));
+        for (curve_name, curve) in CURVES {
+            for (oversampling_name, oversampling) in OVERSAMPLING {
+                for block_size in BLOCK_SIZES {
+                    let params = ClipEngineParams {
+                        curve,
+                        oversampling,
+                        threshold: -6.0,
+                        ..ClipEngineParams::default()
+                    };
+                    let mut engine = ClipEngine::new(SAMPLE_RATE, num_channels, params);
+                    group.bench_function(
+                        BenchmarkId::new(format!("{curve_name}/{oversampling_name}"), block_size),
+                        |b| b.iter(|| run(&mut engine, &signal, block_size)),
+                    );
+                }
+            }
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, engine);
+criterion_main!(benches);
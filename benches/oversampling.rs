@@ -0,0 +1,63 @@
+//! Compares running a stereo signal through two scalar FIR `Oversampler`s against a single stereo
+//! one, at 4x with a hard clipper in between. The stereo IIR filters are included for reference.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use rclip::oversample::{FirStage, OsQuality, Oversampler, StereoFirStage, StereoIirStage};
+use std::hint::black_box;
+
+const NUM_SAMPLES: usize = 4096;
+const NUM_STAGES: usize = 2;
+
+fn test_signal() -> Vec<[f32; 2]> {
+    (0..NUM_SAMPLES)
+        .map(|i| {
+            let phase = i as f32 / 48000.0 * std::f32::consts::TAU;
+            [(phase * 440.0).sin() * 1.5, (phase * 660.0).sin() * 1.5]
+        })
+        .collect()
+}
+
+fn oversampling(c: &mut Criterion) {
+    let signal = test_signal();
+
+    let mut group = c.benchmark_group("oversampling_4x_stereo");
+    group.throughput(Throughput::Elements(NUM_SAMPLES as u64));
+
+    let mut left = Oversampler::<FirStage>::new(OsQuality::Normal);
+    let mut right = Oversampler::<FirStage>::new(OsQuality::Normal);
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            for &[l, r] in &signal {
+                black_box(left.process(NUM_STAGES, l, |x| x.clamp(-1.0, 1.0)));
+                black_box(right.process(NUM_STAGES, r, |x| x.clamp(-1.0, 1.0)));
+            }
+        })
+    });
+
+    let mut stereo = Oversampler::<StereoFirStage>::new(OsQuality::Normal);
+    group.bench_function("simd", |b| {
+        b.iter(|| {
+            for &frame in &signal {
+                black_box(stereo.process(NUM_STAGES, frame, |[l, r]| {
+                    [l.clamp(-1.0, 1.0), r.clamp(-1.0, 1.0)]
+                }));
+            }
+        })
+    });
+
+    let mut iir = Oversampler::<StereoIirStage>::new(OsQuality::Normal);
+    group.bench_function("iir", |b| {
+        b.iter(|| {
+            for &frame in &signal {
+                black_box(iir.process(NUM_STAGES, frame, |[l, r]| {
+                    [l.clamp(-1.0, 1.0), r.clamp(-1.0, 1.0)]
+                }));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, oversampling);
+criterion_main!(benches);
@@ -0,0 +1,926 @@
+//! The waveshaping curves used by the clip stage.
+
+use nih_plug::prelude::*;
+use nih_plug::util::{db_to_gain, gain_to_db};
+
+use crate::envelope::{EnvelopeCoefficients, EnvelopeFollower};
+use crate::table::TransferTable;
+
+/// Every waveshaping choice the clip stage offers. All curves are normalized so that they saturate
+/// at, or in the case of the folder stay within, `±ceiling`. New variants need to be added at the
+/// end so existing sessions keep their selection.
+#[derive(Enum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ClipCurve {
+    #[default]
+    #[name = "Hard"]
+    Hard,
+    #[name = "Tanh"]
+    Tanh,
+    #[name = "Cubic"]
+    Cubic,
+    #[name = "Hard (ADAA)"]
+    HardAdaa,
+    #[name = "Arctan"]
+    Arctan,
+    /// Aliases heavily, so the engine runs this with at least 8x oversampling.
+    #[name = "Sine Fold"]
+    SineFold,
+    /// The same goes for this one.
+    #[name = "Wavefold"]
+    Wavefold,
+    /// Not a waveshaper but a very fast peak limiter, see `TransparentClipper`.
+    #[name = "Transparent"]
+    Transparent,
+    #[name = "Polynomial"]
+    Polynomial,
+    /// An arbitrary shape from a lookup table, see `TransferTable`. Unlike the other curves this
+    /// only stays within the ceiling if the table does.
+    #[name = "Table"]
+    Table,
+}
+
+/// The lowest ceiling any curve clips at. Lower ceilings, including zero and negative ones, are
+/// raised to this so the curves never divide by zero. This is -240 dBFS, far below anything the
+/// thresholds can reach.
+pub const MIN_CEILING: f32 = 1.0e-12;
+
+#[inline(always)]
+pub fn hard_clip(signal: f32, ceiling: f32) -> f32 {
+    let ceiling = ceiling.max(MIN_CEILING);
+    signal.clamp(-ceiling, ceiling)
+}
+
+/// Clamps positive excursions to `ceiling_pos` and negative ones to `-ceiling_neg`. Unequal
+/// ceilings shift the waveform's average, so this leaves DC behind that a DC blocker would remove.
+#[inline(always)]
+pub fn hard_clip_asym(signal: f32, ceiling_pos: f32, ceiling_neg: f32) -> f32 {
+    let ceiling_pos = ceiling_pos.max(MIN_CEILING);
+    let ceiling_neg = ceiling_neg.max(MIN_CEILING);
+    signal.clamp(-ceiling_neg, ceiling_pos)
+}
+
+/// The widest knee the knee parameter allows, see `knee_from_db()`.
+pub const MAX_KNEE_DB: f32 = 12.0;
+
+/// Convert a knee width in decibels to the `knee` taken by `clip_with_knee()`. Like a
+/// compressor's knee, the width is the range of input levels that the transition from the straight
+/// line to the flat top spans, so a 6 dB knee starts bending 3.5 dB below the ceiling and reaches
+/// it 2.5 dB above it.
+#[inline]
+pub fn knee_from_db(knee_db: f32) -> f32 {
+    let ratio = db_to_gain(knee_db.clamp(0.0, MAX_KNEE_DB));
+    2.0 * (ratio - 1.0) / (ratio + 1.0)
+}
+
+/// Hard clipping with the corner at the ceiling rounded off. Within `knee * ceiling / 2` of the
+/// ceiling on either side, the straight line and the flat top are joined by a parabola that
+/// matches both in value and slope, so the output never exceeds the ceiling. A knee of zero is
+/// exactly `hard_clip()`.
+#[inline(always)]
+pub fn clip_with_knee(signal: f32, ceiling: f32, knee: f32) -> f32 {
+    let ceiling = ceiling.max(MIN_CEILING);
+    let width = knee_width(ceiling, knee);
+    if width <= 0.0 {
+        return hard_clip(signal, ceiling);
+    }
+
+    let magnitude = signal.abs();
+    if magnitude <= ceiling - width {
+        signal
+    } else if magnitude >= ceiling + width {
+        ceiling.copysign(signal)
+    } else {
+        let overshoot = magnitude - (ceiling - width);
+        (magnitude - overshoot * overshoot / (4.0 * width)).copysign(signal)
+    }
+}
+
+/// Half the width of the knee region around `ceiling`.
+#[inline(always)]
+fn knee_width(ceiling: f32, knee: f32) -> f32 {
+    knee.clamp(0.0, 2.0) * ceiling * 0.5
+}
+
+/// The antiderivative of `clip_with_knee()` for non-negative inputs. The function is odd, so its
+/// antiderivative is even and the negative half mirrors this one. This is continuous at both ends
+/// of the knee, and with a zero knee it reduces to the antiderivative of the plain hard clipper.
+#[inline(always)]
+fn clip_with_knee_antiderivative(magnitude: f64, ceiling: f64, width: f64) -> f64 {
+    let knee_start = ceiling - width;
+    let knee_end = ceiling + width;
+    if magnitude <= knee_start {
+        magnitude * magnitude / 2.0
+    } else if magnitude < knee_end {
+        let overshoot = magnitude - knee_start;
+        magnitude * magnitude / 2.0 - overshoot * overshoot * overshoot / (12.0 * width)
+    } else {
+        let at_knee_end = knee_end * knee_end / 2.0 - 8.0 * width * width / 12.0;
+        at_knee_end + ceiling * (magnitude - knee_end)
+    }
+}
+
+/// A clip curve as a function of the signal `x` and the ceiling `t`, together with its
+/// antiderivative for antialiasing. `apply_clip()` dispatches the stateless curves in `ClipCurve`
+/// to these, and `Adaa` works with any of them, so a new curve only needs to implement this.
+pub trait ClipShape {
+    fn apply(&self, x: f32, t: f32) -> f32;
+
+    /// An antiderivative of `apply()` with respect to `x` that's zero at the origin.
+    fn antiderivative(&self, x: f32, t: f32) -> f32;
+
+    /// `antiderivative()` in double precision. `Adaa` divides the difference between two of
+    /// these by a small input difference, where single precision falls apart, so shapes should
+    /// override this with a proper double precision version. The default only widens the result.
+    fn antiderivative_f64(&self, x: f64, t: f64) -> f64 {
+        self.antiderivative(x as f32, t as f32) as f64
+    }
+}
+
+/// `clip_with_knee()`, which with a knee of zero is the plain hard clipper.
+#[derive(Clone, Copy, Debug)]
+pub struct HardShape {
+    pub knee: f32,
+}
+
+impl ClipShape for HardShape {
+    #[inline(always)]
+    fn apply(&self, x: f32, t: f32) -> f32 {
+        clip_with_knee(x, t, self.knee)
+    }
+
+    #[inline(always)]
+    fn antiderivative(&self, x: f32, t: f32) -> f32 {
+        self.antiderivative_f64(x as f64, t as f64) as f32
+    }
+
+    #[inline(always)]
+    fn antiderivative_f64(&self, x: f64, t: f64) -> f64 {
+        let ceiling = (t as f32).max(MIN_CEILING);
+        let width = knee_width(ceiling, self.knee);
+        clip_with_knee_antiderivative(x.abs(), ceiling as f64, width as f64)
+    }
+}
+
+/// `soft_clip_tanh()`.
+#[derive(Clone, Copy, Debug)]
+pub struct TanhShape;
+
+impl ClipShape for TanhShape {
+    #[inline(always)]
+    fn apply(&self, x: f32, t: f32) -> f32 {
+        soft_clip_tanh(x, t)
+    }
+
+    #[inline(always)]
+    fn antiderivative(&self, x: f32, t: f32) -> f32 {
+        self.antiderivative_f64(x as f64, t as f64) as f32
+    }
+
+    /// `t² * ln(cosh(x / t))`, written so it doesn't overflow for large arguments.
+    #[inline(always)]
+    fn antiderivative_f64(&self, x: f64, t: f64) -> f64 {
+        let t = t.max(MIN_CEILING as f64);
+        let u = (x / t).abs();
+        t * t * (u + (-2.0 * u).exp().ln_1p() - std::f64::consts::LN_2)
+    }
+}
+
+/// `soft_clip_cubic()`.
+#[derive(Clone, Copy, Debug)]
+pub struct CubicShape;
+
+impl ClipShape for CubicShape {
+    #[inline(always)]
+    fn apply(&self, x: f32, t: f32) -> f32 {
+        soft_clip_cubic(x, t)
+    }
+
+    #[inline(always)]
+    fn antiderivative(&self, x: f32, t: f32) -> f32 {
+        self.antiderivative_f64(x as f64, t as f64) as f32
+    }
+
+    /// This integrates the three pieces of the curve separately, in terms of the normalized
+    /// magnitude.
+    #[inline(always)]
+    fn antiderivative_f64(&self, x: f64, t: f64) -> f64 {
+        let t = t.max(MIN_CEILING as f64);
+        let u = (x / t).abs();
+        let normalized = if u <= 2.0 / 3.0 {
+            u * u / 2.0
+        } else if u < 7.0 / 6.0 {
+            let v = 2.0 * u - 4.0 / 3.0;
+            2.0 / 9.0 + v / 3.0 + v * v / 8.0 - v * v * v * v / 48.0
+        } else {
+            95.0 / 144.0 + (u - 7.0 / 6.0)
+        };
+        t * t * normalized
+    }
+}
+
+/// `soft_clip_arctan()`.
+#[derive(Clone, Copy, Debug)]
+pub struct ArctanShape;
+
+impl ClipShape for ArctanShape {
+    #[inline(always)]
+    fn apply(&self, x: f32, t: f32) -> f32 {
+        soft_clip_arctan(x, t)
+    }
+
+    #[inline(always)]
+    fn antiderivative(&self, x: f32, t: f32) -> f32 {
+        self.antiderivative_f64(x as f64, t as f64) as f32
+    }
+
+    #[inline(always)]
+    fn antiderivative_f64(&self, x: f64, t: f64) -> f64 {
+        let t = t.max(MIN_CEILING as f64);
+        let drive = ARCTAN_DRIVE as f64;
+        let u = (x / t).abs();
+        let normalized = u * (drive * u).atan() - (drive * u).powi(2).ln_1p() / (2.0 * drive);
+        t * t * std::f64::consts::FRAC_2_PI * normalized
+    }
+}
+
+/// `sine_fold()`.
+#[derive(Clone, Copy, Debug)]
+pub struct SineFoldShape;
+
+impl ClipShape for SineFoldShape {
+    #[inline(always)]
+    fn apply(&self, x: f32, t: f32) -> f32 {
+        sine_fold(x, t)
+    }
+
+    #[inline(always)]
+    fn antiderivative(&self, x: f32, t: f32) -> f32 {
+        self.antiderivative_f64(x as f64, t as f64) as f32
+    }
+
+    #[inline(always)]
+    fn antiderivative_f64(&self, x: f64, t: f64) -> f64 {
+        let t = t.max(MIN_CEILING as f64);
+        let half_pi = std::f64::consts::FRAC_PI_2;
+        t * t * (1.0 - (x / t * half_pi).cos()) / half_pi
+    }
+}
+
+/// `wavefold()`.
+#[derive(Clone, Copy, Debug)]
+pub struct WavefoldShape {
+    pub folds: f32,
+}
+
+impl ClipShape for WavefoldShape {
+    #[inline(always)]
+    fn apply(&self, x: f32, t: f32) -> f32 {
+        wavefold(x, t, self.folds)
+    }
+
+    #[inline(always)]
+    fn antiderivative(&self, x: f32, t: f32) -> f32 {
+        self.antiderivative_f64(x as f64, t as f64) as f32
+    }
+
+    /// The triangle wave averages out to zero over every period, so its antiderivative is
+    /// periodic as well: a parabola opening upwards around the zero crossings on the way up and
+    /// one opening downwards around those on the way down.
+    #[inline(always)]
+    fn antiderivative_f64(&self, x: f64, t: f64) -> f64 {
+        let t = t.max(MIN_CEILING as f64);
+        let folds = self.folds as f64;
+        let phase = (x / t * folds + 1.0).rem_euclid(4.0);
+        let normalized = if phase < 2.0 {
+            (phase - 1.0).powi(2) / 2.0
+        } else {
+            1.0 - (3.0 - phase).powi(2) / 2.0
+        };
+        t * t * normalized / folds
+    }
+}
+
+/// `polynomial_clip()`.
+#[derive(Clone, Copy, Debug)]
+pub struct PolynomialShape {
+    pub order: f32,
+}
+
+impl ClipShape for PolynomialShape {
+    #[inline(always)]
+    fn apply(&self, x: f32, t: f32) -> f32 {
+        polynomial_clip(x, t, self.order)
+    }
+
+    #[inline(always)]
+    fn antiderivative(&self, x: f32, t: f32) -> f32 {
+        self.antiderivative_f64(x as f64, t as f64) as f32
+    }
+
+    /// Below the ceiling this integrates to `t²(u - (1 - (1 - u)^(n+1)) / (n + 1))` for the
+    /// normalized magnitude `u`, and past it the curve is flat so the antiderivative goes on as a
+    /// straight line.
+    #[inline(always)]
+    fn antiderivative_f64(&self, x: f64, t: f64) -> f64 {
+        let t = t.max(MIN_CEILING as f64);
+        let order = self.order.max(1.0) as f64;
+        let u = (x / t).abs();
+        let at = |u: f64| u - (1.0 - (1.0 - u).powf(order + 1.0)) / (order + 1.0);
+        let normalized = if u < 1.0 { at(u) } else { at(1.0) + (u - 1.0) };
+        t * t * normalized
+    }
+}
+
+/// `TransferTable::lookup()` for the normalized magnitude, scaled back up by the ceiling.
+#[derive(Clone, Copy, Debug)]
+pub struct TableShape<'a> {
+    pub table: &'a TransferTable,
+}
+
+impl ClipShape for TableShape<'_> {
+    #[inline(always)]
+    fn apply(&self, x: f32, t: f32) -> f32 {
+        let t = t.max(MIN_CEILING);
+        let magnitude = self.table.lookup(x.abs() / t) * t;
+        // Not `copysign()`, since a table can go below zero
+        if x < 0.0 { -magnitude } else { magnitude }
+    }
+
+    #[inline(always)]
+    fn antiderivative(&self, x: f32, t: f32) -> f32 {
+        self.antiderivative_f64(x as f64, t as f64) as f32
+    }
+
+    #[inline(always)]
+    fn antiderivative_f64(&self, x: f64, t: f64) -> f64 {
+        let t = t.max(MIN_CEILING as f64);
+        t * t * self.table.integral((x / t).abs())
+    }
+}
+
+/// First-order antiderivative antialiasing for any `ClipShape`. Instead of clipping each sample,
+/// this outputs the average of the clip function over the line segment between the previous and
+/// the current input, `(F(x[n]) - F(x[n-1])) / (x[n] - x[n-1])`. That suppresses most of the
+/// aliasing without oversampling, at the cost of a half sample delay and a slight high frequency
+/// roll-off.
+#[derive(Clone, Copy, Default)]
+pub struct Adaa {
+    x1: f32,
+}
+
+impl Adaa {
+    /// Below this input difference the quotient becomes numerically unstable, so the clip function
+    /// is evaluated at the segment's midpoint instead.
+    const EPSILON: f64 = 1.0e-5;
+
+    /// Clip with separate ceilings for both polarities. All shapes are odd functions, so their
+    /// antiderivatives for both ceilings meet at the origin.
+    #[inline(always)]
+    pub fn process(
+        &mut self,
+        shape: &impl ClipShape,
+        signal: f32,
+        ceiling_pos: f32,
+        ceiling_neg: f32,
+    ) -> f32 {
+        let ceiling_pos = ceiling_pos.max(MIN_CEILING);
+        let ceiling_neg = ceiling_neg.max(MIN_CEILING);
+        let (x0, x1) = (signal as f64, self.x1 as f64);
+        self.x1 = signal;
+
+        let difference = x0 - x1;
+        if difference.abs() < Self::EPSILON {
+            let midpoint = (signal + x1 as f32) / 2.0;
+            let ceiling = if midpoint >= 0.0 {
+                ceiling_pos
+            } else {
+                ceiling_neg
+            };
+            shape.apply(midpoint, ceiling)
+        } else {
+            let antiderivative = |x: f64| {
+                let ceiling = if x >= 0.0 { ceiling_pos } else { ceiling_neg };
+                shape.antiderivative_f64(x, ceiling as f64)
+            };
+            ((antiderivative(x0) - antiderivative(x1)) / difference) as f32
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Reduces the peaks with a gain that follows the signal's envelope instead of reshaping the
+/// waveform. Between peaks the gain recovers over the release time, so the body of the signal
+/// keeps its level and most of its crest factor, and the distortion is limited to the gain change
+/// itself. The envelope rises over the attack time, so whatever gets ahead of it is caught by a
+/// plain hard clip at the ceiling. Use the lookahead to give the envelope a head start on those.
+#[derive(Clone, Copy, Default)]
+pub struct TransparentClipper {
+    envelope: EnvelopeFollower,
+}
+
+impl TransparentClipper {
+    /// The envelope's `coefficients` need to be for the clip stage's rate.
+    #[inline(always)]
+    pub fn process(
+        &mut self,
+        signal: f32,
+        ceiling_pos: f32,
+        ceiling_neg: f32,
+        coefficients: &EnvelopeCoefficients,
+    ) -> f32 {
+        let ceiling_pos = ceiling_pos.max(MIN_CEILING);
+        let ceiling_neg = ceiling_neg.max(MIN_CEILING);
+        let ceiling = if signal >= 0.0 {
+            ceiling_pos
+        } else {
+            ceiling_neg
+        };
+
+        let envelope = self.envelope.process(coefficients, signal);
+        let limited = if envelope > ceiling {
+            signal * (ceiling / envelope)
+        } else {
+            signal
+        };
+        hard_clip_asym(limited, ceiling_pos, ceiling_neg)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// A lightweight approximation of the hysteresis in analog clippers. Instead of taking the signal
+/// down to the curve's output right away, the amount being removed follows the curve through a
+/// one-pole low-pass. Rising edges briefly overshoot and the clipping lingers a little on the way
+/// back down, so the transfer function traces a loop that gets wider with frequency. Below the
+/// threshold nothing is being removed, so quiet signals pass through unchanged.
+#[derive(Clone, Copy, Default)]
+pub struct Hysteresis {
+    /// The smoothed difference between the curve's output and its input.
+    reduction: f32,
+}
+
+impl Hysteresis {
+    /// Above roughly this frequency the changes in the amount being clipped get smeared out.
+    const CUTOFF_HZ: f32 = 5_000.0;
+
+    /// The one-pole smoothing factor at `sample_rate`, which is the clip stage's rate.
+    pub fn coefficient(sample_rate: f32) -> f32 {
+        1.0 - (-std::f32::consts::TAU * Self::CUTOFF_HZ / sample_rate).exp()
+    }
+
+    /// Take `clipped`, the curve's output for `signal`, and return it with the hysteresis applied.
+    #[inline(always)]
+    pub fn process(&mut self, signal: f32, clipped: f32, coefficient: f32) -> f32 {
+        self.reduction += coefficient * ((clipped - signal) - self.reduction);
+        signal + self.reduction
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Lets the clipping relax over a release time instead of letting go the moment the signal drops
+/// back below the curve. The amount the curve takes off the signal's magnitude is held and decays
+/// with a one-pole release, and whatever the held amount exceeds the current one by comes off the
+/// curve's output on top. That rounds off the trailing edge of every clipped transient without
+/// touching its peak, and it never pushes the output past the curve's own output or across zero.
+#[derive(Clone, Copy, Default)]
+pub struct ClipRelease {
+    /// The held reduction in the signal's magnitude.
+    reduction: f32,
+}
+
+impl ClipRelease {
+    /// The one-pole release factor for `release_ms` at `sample_rate`, which is the clip stage's
+    /// rate. A release time of zero lets go instantly.
+    pub fn coefficient(sample_rate: f32, release_ms: f32) -> f32 {
+        let samples = release_ms / 1000.0 * sample_rate;
+        if samples > 0.0 {
+            (-samples.recip()).exp()
+        } else {
+            0.0
+        }
+    }
+
+    /// Take `clipped`, the curve's output for `signal`, and return it with the held reduction
+    /// applied. With a zero `coefficient` this returns `clipped` unchanged.
+    #[inline(always)]
+    pub fn process(&mut self, signal: f32, clipped: f32, coefficient: f32) -> f32 {
+        let reduction = signal.abs() - clipped.abs();
+        self.reduction = if reduction >= self.reduction {
+            reduction
+        } else {
+            reduction + coefficient * (self.reduction - reduction)
+        };
+        if coefficient <= 0.0 {
+            return clipped;
+        }
+
+        let extra = self.reduction - reduction;
+        (clipped.abs() - extra).max(0.0).copysign(clipped)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Saturates towards `±ceiling` without ever reaching it. `tanh` of an infinite argument is still
+/// `±1`, so the ceiling floor keeps this finite even for vanishingly small thresholds.
+#[inline(always)]
+pub fn soft_clip_tanh(signal: f32, ceiling: f32) -> f32 {
+    let ceiling = ceiling.max(MIN_CEILING);
+    let x = (signal / ceiling).tanh();
+    x * ceiling
+}
+
+/// Passes the signal untouched up to two thirds of the ceiling, then eases into `±ceiling` with
+/// the classic `x - x³/3` curve, rescaled so value and slope are continuous at both knees. Full
+/// saturation is reached at 7/6 of the ceiling.
+#[inline(always)]
+pub fn soft_clip_cubic(signal: f32, ceiling: f32) -> f32 {
+    let ceiling = ceiling.max(MIN_CEILING);
+    let x = signal.abs() / ceiling;
+    if x <= 2.0 / 3.0 {
+        signal
+    } else if x >= 7.0 / 6.0 {
+        ceiling.copysign(signal)
+    } else {
+        let v = 2.0 * x - 4.0 / 3.0;
+        ((2.0 / 3.0 + 0.5 * (v - v * v * v / 3.0)) * ceiling).copysign(signal)
+    }
+}
+
+/// The factor the normalized signal is scaled by before the arctangent in `soft_clip_arctan()`.
+/// Combined with the 2/π output scaling this gives the curve unity gain around the origin, so
+/// quiet signals come out at the same level as with the other curves.
+const ARCTAN_DRIVE: f32 = std::f32::consts::FRAC_PI_2;
+
+/// Like `soft_clip_tanh()` but with a gentler approach towards `±ceiling`. A signal right at the
+/// ceiling comes out about 3.9 dB below it, where tanh would be 2.4 dB below, and the curve only
+/// gets within 1 dB of the ceiling at around four times its level. `atan()` stays finite for any
+/// argument, so vanishingly small ceilings just turn this into a hard clipper.
+#[inline(always)]
+pub fn soft_clip_arctan(signal: f32, ceiling: f32) -> f32 {
+    let ceiling = ceiling.max(MIN_CEILING);
+    let x = (signal / ceiling * ARCTAN_DRIVE).atan();
+    x * std::f32::consts::FRAC_2_PI * ceiling
+}
+
+/// A sine shaper that reaches `±ceiling` when the input does, and folds back down instead of
+/// clamping as soon as the input goes past that.
+#[inline(always)]
+pub fn sine_fold(signal: f32, ceiling: f32) -> f32 {
+    let ceiling = ceiling.max(MIN_CEILING);
+    (signal / ceiling * std::f32::consts::FRAC_PI_2).sin() * ceiling
+}
+
+/// Reflects the signal back and forth between `±ceiling` instead of clamping it. `folds` scales
+/// the signal going into the folder, so at higher values even quiet signals fold over several times.
+/// The sharp corners at every reflection alias heavily without oversampling.
+#[inline(always)]
+pub fn wavefold(signal: f32, ceiling: f32, folds: f32) -> f32 {
+    let ceiling = ceiling.max(MIN_CEILING);
+    // This maps the normalized signal onto a triangle wave with a period of four, which passes
+    // through the origin with unity slope and peaks at `±1`
+    let phase = (signal / ceiling * folds + 1.0).rem_euclid(4.0);
+    let folded = if phase < 2.0 {
+        phase - 1.0
+    } else {
+        3.0 - phase
+    };
+    folded * ceiling
+}
+
+/// `ceiling * (1 - (1 - |x| / ceiling)^order)` with the signal's sign below the ceiling, and
+/// `±ceiling` from there on. An order of one is linear all the way up to the ceiling, which is the
+/// plain hard clipper. Higher orders start out with a slope of `order` and bend over into the
+/// ceiling before the signal gets there, so the curve saturates earlier and harder, heading
+/// towards a square wave. Orders below one are treated as one.
+#[inline(always)]
+pub fn polynomial_clip(signal: f32, ceiling: f32, order: f32) -> f32 {
+    let ceiling = ceiling.max(MIN_CEILING);
+    let u = (signal.abs() / ceiling).min(1.0);
+    (ceiling * (1.0 - (1.0 - u).powf(order.max(1.0)))).copysign(signal)
+}
+
+/// Which polarities the clip stage acts on. Clipping only one side leaves a DC offset behind, which
+/// the DC blocker takes care of.
+#[derive(Enum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ClipSide {
+    #[default]
+    #[name = "Both"]
+    Both,
+    #[name = "Positive Only"]
+    PositiveOnly,
+    #[name = "Negative Only"]
+    NegativeOnly,
+}
+
+impl ClipSide {
+    /// Whether a sample with this polarity goes through the curve at all.
+    #[inline(always)]
+    pub fn clips(self, signal: f32) -> bool {
+        match self {
+            ClipSide::Both => true,
+            ClipSide::PositiveOnly => signal >= 0.0,
+            ClipSide::NegativeOnly => signal < 0.0,
+        }
+    }
+
+    /// Raise the ceiling of the side that isn't clipped out of reach. Stateful clippers like
+    /// `Adaa` can't just skip samples, so they get these ceilings instead and their output for the
+    /// untouched side is replaced with the input.
+    #[inline(always)]
+    pub fn ceilings(self, ceiling_pos: f32, ceiling_neg: f32) -> (f32, f32) {
+        match self {
+            ClipSide::Both => (ceiling_pos, ceiling_neg),
+            ClipSide::PositiveOnly => (ceiling_pos, f32::MAX),
+            ClipSide::NegativeOnly => (f32::MAX, ceiling_neg),
+        }
+    }
+}
+
+/// Everything besides the ceilings that determines the clip stage's shape.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipSettings<'a> {
+    pub curve: ClipCurve,
+    pub side: ClipSide,
+    /// Only used by the hard clipper, see `clip_with_knee()` and `knee_from_db()`.
+    pub knee: f32,
+    /// Only used by the wavefolder, see `wavefold()`.
+    pub folds: f32,
+    /// Only used by the polynomial curve, see `polynomial_clip()`.
+    pub order: f32,
+    /// Only used by the table curve, see `TableShape`.
+    pub table: &'a TransferTable,
+    /// Only used by the hard clipper, see `ClipSettings::saturate()`.
+    pub saturation: f32,
+    /// The two-stage clipper's saturation drive as a gain of at least one, and how much of it is
+    /// faded in, see `ClipSettings::presaturate()`.
+    pub sat_drive: f32,
+    pub two_stage: f32,
+}
+
+impl ClipSettings<'_> {
+    /// Crossfade the hard clipper's output `hard` for `signal` towards `soft_clip_tanh()` at the
+    /// same ceiling, from pure hard clipping when `saturation` is zero to pure tanh at one.
+    #[inline(always)]
+    pub fn saturate(&self, hard: f32, signal: f32, ceiling_pos: f32, ceiling_neg: f32) -> f32 {
+        if self.saturation <= 0.0 {
+            return hard;
+        }
+
+        let ceiling = if signal >= 0.0 {
+            ceiling_pos
+        } else {
+            ceiling_neg
+        };
+        hard + self.saturation * (soft_clip_tanh(signal, ceiling) - hard)
+    }
+
+    /// The first half of the two-stage clipper, run on the signal before it goes through the
+    /// curve: `ceiling * tanh(drive * signal / ceiling) / tanh(drive)`. This rounds off the signal
+    /// on its way up to the ceiling and meets it exactly at the threshold, where the curve takes
+    /// over and stops anything above it. Low levels come out `drive` times louder, so like the
+    /// main drive this pushes more of the signal into the saturation. Samples on a side that
+    /// `side` excludes are passed through untouched, and `two_stage` crossfades between the
+    /// dry and the saturated signal.
+    #[inline(always)]
+    pub fn presaturate(&self, signal: f32, ceiling_pos: f32, ceiling_neg: f32) -> f32 {
+        if self.two_stage <= 0.0 || !self.side.clips(signal) {
+            return signal;
+        }
+
+        let ceiling = if signal >= 0.0 {
+            ceiling_pos
+        } else {
+            ceiling_neg
+        }
+        .max(MIN_CEILING);
+        let saturated =
+            ceiling * (self.sat_drive * signal / ceiling).tanh() / self.sat_drive.tanh();
+        signal + self.two_stage * (saturated - signal)
+    }
+}
+
+/// The highest magnitude the hard clipper with `knee` passes through exactly as it is, where its
+/// knee starts below `ceiling`.
+#[inline]
+pub fn knee_start(ceiling: f32, knee: f32) -> f32 {
+    let ceiling = ceiling.max(MIN_CEILING);
+    ceiling - knee_width(ceiling, knee)
+}
+
+/// Run `signal` through the selected curve, clipping positive and negative excursions at their own
+/// ceilings. Samples on a side that `settings.side` excludes are passed through untouched.
+#[inline(always)]
+pub fn apply_clip(settings: &ClipSettings, signal: f32, ceiling_pos: f32, ceiling_neg: f32) -> f32 {
+    if !settings.side.clips(signal) {
+        return signal;
+    }
+
+    // All curves are odd functions, so picking the ceiling by polarity keeps them continuous
+    // through the origin
+    let ceiling = if signal >= 0.0 {
+        ceiling_pos
+    } else {
+        ceiling_neg
+    };
+    let knee = settings.knee;
+    match settings.curve {
+        // The antialiased version needs per-channel state and is handled in `process()`, these are
+        // its stateless equivalents
+        ClipCurve::Hard | ClipCurve::HardAdaa => {
+            let hard = HardShape { knee }.apply(signal, ceiling);
+            settings.saturate(hard, signal, ceiling_pos, ceiling_neg)
+        }
+        ClipCurve::Tanh => TanhShape.apply(signal, ceiling),
+        ClipCurve::Cubic => CubicShape.apply(signal, ceiling),
+        ClipCurve::Arctan => ArctanShape.apply(signal, ceiling),
+        ClipCurve::SineFold => SineFoldShape.apply(signal, ceiling),
+        ClipCurve::Wavefold => WavefoldShape {
+            folds: settings.folds,
+        }
+        .apply(signal, ceiling),
+        ClipCurve::Polynomial => PolynomialShape {
+            order: settings.order,
+        }
+        .apply(signal, ceiling),
+        ClipCurve::Table => TableShape {
+            table: settings.table,
+        }
+        .apply(signal, ceiling),
+        // The limiter's gain needs per-channel state as well, and with a static input it settles
+        // on the same output as the hard clipper
+        ClipCurve::Transparent => hard_clip_asym(signal, ceiling_pos, ceiling_neg),
+    }
+}
+
+/// How much `apply_clip()` takes off `signal`, in positive decibels. This is the curve's static
+/// reduction for that input rather than a comparison with the clip stage's actual output, so it
+/// rises gradually through the knee instead of jumping at the threshold, and it isn't thrown off by
+/// the oversampling filters' ripple and phase shift near the zero crossings.
+#[inline]
+pub fn static_gain_reduction_db(
+    settings: &ClipSettings,
+    signal: f32,
+    ceiling_pos: f32,
+    ceiling_neg: f32,
+) -> f32 {
+    let magnitude = signal.abs();
+    let saturated = settings.presaturate(signal, ceiling_pos, ceiling_neg);
+    let clipped = apply_clip(settings, saturated, ceiling_pos, ceiling_neg).abs();
+    if clipped < magnitude {
+        gain_to_db(magnitude) - gain_to_db(clipped)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CEILING: f32 = 0.5;
+
+    #[test]
+    fn cubic_knee_points() {
+        let start = CEILING * 2.0 / 3.0;
+        let end = CEILING * 7.0 / 6.0;
+        assert_eq!(soft_clip_cubic(start, CEILING), start);
+        assert_eq!(soft_clip_cubic(-start, CEILING), -start);
+        assert_eq!(soft_clip_cubic(end, CEILING), CEILING);
+        assert_eq!(soft_clip_cubic(-end, CEILING), -CEILING);
+    }
+
+    #[test]
+    fn cubic_continuity() {
+        let slope = |x: f32| {
+            let h = 1.0e-3 * CEILING;
+            (soft_clip_cubic(x + h, CEILING) - soft_clip_cubic(x - h, CEILING)) / (2.0 * h)
+        };
+        let epsilon = 1.0e-5 * CEILING;
+
+        for (knee, expected_value, expected_slope) in [
+            (CEILING * 2.0 / 3.0, CEILING * 2.0 / 3.0, 1.0),
+            (CEILING * 7.0 / 6.0, CEILING, 0.0),
+        ] {
+            let below = soft_clip_cubic(knee - epsilon, CEILING);
+            let above = soft_clip_cubic(knee + epsilon, CEILING);
+            assert!((below - expected_value).abs() < 1.0e-4, "{knee}: {below}");
+            assert!((above - expected_value).abs() < 1.0e-4, "{knee}: {above}");
+            assert!((slope(knee) - expected_slope).abs() < 1.0e-2, "{knee}");
+        }
+    }
+
+    #[test]
+    fn zero_knee_is_hard_clip() {
+        let knee = knee_from_db(0.0);
+        assert_eq!(knee, 0.0);
+        for step in -400..=400 {
+            let signal = step as f32 / 100.0;
+            assert_eq!(
+                clip_with_knee(signal, CEILING, knee).to_bits(),
+                hard_clip(signal, CEILING).to_bits(),
+                "{signal}"
+            );
+        }
+    }
+
+    #[test]
+    fn polynomial_order_one_is_hard_clip() {
+        // Orders below one are treated as one
+        for order in [1.0, 0.5, 0.0] {
+            for step in -400..=400 {
+                let signal = step as f32 / 100.0;
+                let polynomial = polynomial_clip(signal, CEILING, order);
+                let hard = hard_clip(signal, CEILING);
+                assert!((polynomial - hard).abs() < 1.0e-6, "{order}, {signal}");
+            }
+        }
+    }
+
+    #[test]
+    fn high_polynomial_order_approaches_a_square_wave() {
+        let order = 1000.0;
+        assert_eq!(polynomial_clip(0.0, CEILING, order), 0.0);
+        let mut previous = 0.0;
+        for step in 1..=400 {
+            let signal = step as f32 / 100.0;
+            let clipped = polynomial_clip(signal, CEILING, order);
+            assert!(
+                clipped >= previous && clipped <= CEILING,
+                "{signal}: {clipped}"
+            );
+            assert_eq!(polynomial_clip(-signal, CEILING, order), -clipped);
+            previous = clipped;
+        }
+        // Already a hundredth of the way up to the ceiling it's all but there
+        let clipped = polynomial_clip(CEILING / 100.0, CEILING, order);
+        assert!(clipped > CEILING * 0.9999, "{clipped}");
+    }
+
+    #[test]
+    fn untouched_polarity_is_bit_identical() {
+        let table = TransferTable::default();
+        for (side, untouched) in [
+            (ClipSide::PositiveOnly, -1.0f32),
+            (ClipSide::NegativeOnly, 1.0f32),
+        ] {
+            for curve in (0..ClipCurve::variants().len()).map(ClipCurve::from_index) {
+                let settings = ClipSettings {
+                    curve,
+                    side,
+                    knee: knee_from_db(6.0),
+                    folds: 2.0,
+                    order: 4.0,
+                    table: &table,
+                    saturation: 0.5,
+                    sat_drive: 2.0,
+                    two_stage: 1.0,
+                };
+                for step in 1..=400 {
+                    let signal = untouched * step as f32 / 100.0;
+                    let saturated = settings.presaturate(signal, CEILING, CEILING);
+                    let clipped = apply_clip(&settings, saturated, CEILING, CEILING);
+                    assert_eq!(clipped.to_bits(), signal.to_bits(), "{side:?} {curve:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn arctan_onset_slope_and_ceiling() {
+        // Unity gain around the origin, like the other curves
+        let slope = soft_clip_arctan(1.0e-4 * CEILING, CEILING) / (1.0e-4 * CEILING);
+        assert!((slope - 1.0).abs() < 1.0e-4, "{slope}");
+
+        // The soft onset leaves a signal at the ceiling about 3.9 dB below it, and only gets within
+        // 1 dB of the ceiling at around four times that level
+        let at_ceiling_db = gain_to_db(soft_clip_arctan(CEILING, CEILING) / CEILING);
+        assert!((at_ceiling_db + 3.9).abs() < 0.05, "{at_ceiling_db}");
+        let below_db = gain_to_db(soft_clip_arctan(3.5 * CEILING, CEILING) / CEILING);
+        let above_db = gain_to_db(soft_clip_arctan(4.5 * CEILING, CEILING) / CEILING);
+        assert!(below_db < -1.0 && above_db > -1.0, "{below_db} {above_db}");
+
+        // Continuous and rising, without ever reaching the ceiling
+        let mut previous = soft_clip_arctan(-10.0 * CEILING, CEILING);
+        for step in -999..=1000 {
+            let signal = step as f32 / 100.0 * CEILING;
+            let output = soft_clip_arctan(signal, CEILING);
+            assert!(output > previous, "{signal}");
+            assert!(output - previous < 0.01 * CEILING, "{signal}");
+            assert!(output.abs() < CEILING, "{signal}");
+            previous = output;
+        }
+        assert!(soft_clip_arctan(f32::MAX, CEILING) <= CEILING);
+        assert!(soft_clip_arctan(1.0, 0.0).is_finite());
+    }
+}
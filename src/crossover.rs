@@ -0,0 +1,67 @@
+//! A three-band Linkwitz-Riley crossover for the multiband clipping mode.
+//!
+//! Every split uses fourth order Linkwitz-Riley filters, made from two cascaded Butterworth
+//! biquads, whose low-pass and high-pass outputs sum to an allpass. The low band additionally
+//! runs through the allpass of the upper crossover so all three bands end up with the same phase
+//! response, and summing them back together gives the input with nothing but a phase shift. None
+//! of this adds latency.
+
+use crate::filter::{BUTTERWORTH_Q, Biquad, BiquadCoefficients};
+
+/// The filter coefficients for both crossover points, shared by all channels.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CrossoverCoefficients {
+    low_lowpass: BiquadCoefficients,
+    low_highpass: BiquadCoefficients,
+    high_lowpass: BiquadCoefficients,
+    high_highpass: BiquadCoefficients,
+    /// The sum of the upper crossover's outputs, used to phase align the low band.
+    high_allpass: BiquadCoefficients,
+}
+
+impl CrossoverCoefficients {
+    /// `high_hz` should be above `low_hz`, otherwise the mid band ends up empty.
+    pub fn new(sample_rate: f32, low_hz: f32, high_hz: f32) -> Self {
+        Self {
+            low_lowpass: BiquadCoefficients::lowpass(sample_rate, low_hz, BUTTERWORTH_Q),
+            low_highpass: BiquadCoefficients::highpass(sample_rate, low_hz, BUTTERWORTH_Q),
+            high_lowpass: BiquadCoefficients::lowpass(sample_rate, high_hz, BUTTERWORTH_Q),
+            high_highpass: BiquadCoefficients::highpass(sample_rate, high_hz, BUTTERWORTH_Q),
+            high_allpass: BiquadCoefficients::allpass(sample_rate, high_hz, BUTTERWORTH_Q),
+        }
+    }
+}
+
+/// A single channel's crossover state.
+#[derive(Clone, Copy, Default)]
+pub struct Crossover {
+    low_lowpass: [Biquad; 2],
+    low_highpass: [Biquad; 2],
+    high_lowpass: [Biquad; 2],
+    high_highpass: [Biquad; 2],
+    low_allpass: Biquad,
+}
+
+impl Crossover {
+    /// Split a sample into its low, mid, and high bands.
+    #[inline]
+    pub fn split(&mut self, coefficients: &CrossoverCoefficients, sample: f32) -> [f32; 3] {
+        let low = cascade(&mut self.low_lowpass, &coefficients.low_lowpass, sample);
+        let rest = cascade(&mut self.low_highpass, &coefficients.low_highpass, sample);
+        let mid = cascade(&mut self.high_lowpass, &coefficients.high_lowpass, rest);
+        let high = cascade(&mut self.high_highpass, &coefficients.high_highpass, rest);
+        let low = self.low_allpass.process(&coefficients.high_allpass, low);
+
+        [low, mid, high]
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[inline(always)]
+fn cascade(filters: &mut [Biquad; 2], coefficients: &BiquadCoefficients, sample: f32) -> f32 {
+    let sample = filters[0].process(coefficients, sample);
+    filters[1].process(coefficients, sample)
+}
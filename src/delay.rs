@@ -0,0 +1,37 @@
+//! A fixed capacity delay line for time-aligning signals.
+
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    write_pos: usize,
+}
+
+impl DelayLine {
+    /// Create a delay line that can delay by up to `max_delay` samples.
+    pub fn new(max_delay: usize) -> Self {
+        Self {
+            buffer: vec![0.0; max_delay + 1],
+            write_pos: 0,
+        }
+    }
+
+    pub fn max_delay(&self) -> usize {
+        self.buffer.len() - 1
+    }
+
+    /// Write `sample` and return the sample that was written `delay` samples ago. `delay` is
+    /// clamped to the capacity, and a delay of zero returns `sample` itself.
+    #[inline]
+    pub fn process(&mut self, sample: f32, delay: usize) -> f32 {
+        let len = self.buffer.len();
+        self.buffer[self.write_pos] = sample;
+        let read_pos = (self.write_pos + len - delay.min(len - 1)) % len;
+        self.write_pos = (self.write_pos + 1) % len;
+
+        self.buffer[read_pos]
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.write_pos = 0;
+    }
+}
@@ -0,0 +1,68 @@
+//! Flushing denormals to zero while processing.
+
+/// Enables the CPU's flush-to-zero mode for as long as this is alive, restoring the previous mode
+/// when it's dropped. The IIR filters' feedback paths decay into denormals whenever the input goes
+/// silent, and on most CPUs arithmetic on those is many times slower than on normal floats. Hosts
+/// often enable this mode already, but the engine can't rely on that. On architectures other than
+/// x86_64 and AArch64 this does nothing.
+pub struct ScopedFtz {
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    previous: u64,
+}
+
+impl ScopedFtz {
+    /// The MXCSR register's flush-to-zero and denormals-are-zero bits.
+    #[cfg(target_arch = "x86_64")]
+    const FLAGS: u64 = (1 << 15) | (1 << 6);
+    /// The FPCR register's flush-to-zero bit.
+    #[cfg(target_arch = "aarch64")]
+    const FLAGS: u64 = 1 << 24;
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn enable() -> Self {
+        let mut mxcsr: u32 = 0;
+        // SAFETY: This only changes how denormals are treated, which Rust doesn't make any
+        //         guarantees about, and the previous state is restored on drop
+        unsafe {
+            std::arch::asm!("stmxcsr [{}]", in(reg) &mut mxcsr, options(nostack));
+            let flushing = mxcsr | Self::FLAGS as u32;
+            std::arch::asm!("ldmxcsr [{}]", in(reg) &flushing, options(nostack, readonly));
+        }
+
+        Self {
+            previous: mxcsr as u64,
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn enable() -> Self {
+        let fpcr: u64;
+        // SAFETY: See above
+        unsafe {
+            std::arch::asm!("mrs {}, fpcr", out(reg) fpcr, options(nomem, nostack));
+            std::arch::asm!("msr fpcr, {}", in(reg) fpcr | Self::FLAGS, options(nomem, nostack));
+        }
+
+        Self { previous: fpcr }
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn enable() -> Self {
+        Self {}
+    }
+}
+
+impl Drop for ScopedFtz {
+    fn drop(&mut self) {
+        // SAFETY: This restores the state from before `enable()`
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            let mxcsr = self.previous as u32;
+            std::arch::asm!("ldmxcsr [{}]", in(reg) &mxcsr, options(nostack, readonly));
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            std::arch::asm!("msr fpcr, {}", in(reg) self.previous, options(nomem, nostack));
+        }
+    }
+}
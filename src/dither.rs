@@ -0,0 +1,41 @@
+//! TPDF dither for reducing the output to a lower bit depth.
+
+/// Adds triangular probability density noise spanning one least significant bit in either
+/// direction, which decorrelates the quantization error from the signal when the host or a later
+/// stage truncates the output. Each channel needs its own instance so the noise isn't correlated
+/// between channels.
+pub struct Dither {
+    /// The xorshift32 state. This is never zero.
+    state: u32,
+}
+
+impl Dither {
+    pub fn new(seed: u32) -> Self {
+        // Zero is xorshift's only fixed point, and the multiplication spreads out small seeds
+        Self {
+            state: seed.wrapping_add(1).wrapping_mul(0x9e37_79b9).max(1),
+        }
+    }
+
+    /// The size of one least significant bit for full scale signals at this bit depth.
+    pub fn lsb(bits: i32) -> f32 {
+        2.0f32.powi(1 - bits)
+    }
+
+    /// The next noise sample, between `-lsb` and `lsb`.
+    #[inline]
+    pub fn next(&mut self, lsb: f32) -> f32 {
+        (self.next_uniform() - self.next_uniform()) * lsb
+    }
+
+    /// A uniformly distributed value in `[0, 1)`.
+    #[inline]
+    fn next_uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        // The top 24 bits fit exactly into an `f32`'s mantissa
+        (self.state >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
@@ -0,0 +1,189 @@
+//! The plugin's GUI. Everything in here only reads from the parameters and the meters, so the
+//! DSP works exactly the same without the editor ever being opened.
+
+use atomic_float::AtomicF32;
+use nih_plug::prelude::*;
+use nih_plug_vizia::vizia::prelude::*;
+use nih_plug_vizia::widgets::*;
+use nih_plug_vizia::{ViziaState, ViziaTheming, assets, create_vizia_editor};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::{PluginParams, TRANSFER_CURVE_RANGE};
+
+/// How long the peak meters hold their maximum.
+const PEAK_METER_HOLD_MS: u64 = 600;
+/// How often the editor checks for a threshold suggested by `learn`.
+const LEARN_POLL_INTERVAL_MS: u64 = 100;
+
+#[derive(Lens)]
+struct Data {
+    params: Arc<PluginParams>,
+    input_peak: Arc<AtomicF32>,
+    output_peak: Arc<AtomicF32>,
+    gr_meter: Arc<AtomicF32>,
+}
+
+impl Model for Data {}
+
+pub fn default_state() -> Arc<ViziaState> {
+    ViziaState::new(|| (420, 360))
+}
+
+pub fn create(
+    params: Arc<PluginParams>,
+    input_peak: Arc<AtomicF32>,
+    output_peak: Arc<AtomicF32>,
+    gr_meter: Arc<AtomicF32>,
+    editor_state: Arc<ViziaState>,
+) -> Option<Box<dyn Editor>> {
+    create_vizia_editor(editor_state, ViziaTheming::Custom, move |cx, context| {
+        assets::register_noto_sans_light(cx);
+        assets::register_noto_sans_thin(cx);
+        poll_learned_threshold(cx, params.clone(), context);
+
+        Data {
+            params: params.clone(),
+            input_peak: input_peak.clone(),
+            output_peak: output_peak.clone(),
+            gr_meter: gr_meter.clone(),
+        }
+        .build(cx);
+
+        VStack::new(cx, |cx| {
+            Label::new(cx, "rClip")
+                .font_size(30.0)
+                .height(Pixels(50.0))
+                .child_top(Stretch(1.0))
+                .child_bottom(Pixels(0.0));
+
+            HStack::new(cx, |cx| {
+                VStack::new(cx, |cx| {
+                    Label::new(cx, "Gain");
+                    ParamSlider::new(cx, Data::params, |params| &params.gain);
+                    Label::new(cx, "Threshold");
+                    ParamSlider::new(cx, Data::params, |params| &params.threshold);
+                    Label::new(cx, "Delta");
+                    ParamButton::new(cx, Data::params, |params| &params.delta);
+                })
+                .row_between(Pixels(0.0));
+
+                TransferCurve::new(cx, params.clone())
+                    .width(Pixels(150.0))
+                    .height(Pixels(150.0));
+            })
+            .height(Pixels(160.0))
+            .col_between(Pixels(20.0));
+
+            Label::new(cx, "Input");
+            PeakMeter::new(
+                cx,
+                Data::input_peak.map(|peak| util::gain_to_db(peak.load(Ordering::Relaxed))),
+                Some(Duration::from_millis(PEAK_METER_HOLD_MS)),
+            );
+            Label::new(cx, "Output");
+            PeakMeter::new(
+                cx,
+                Data::output_peak.map(|peak| util::gain_to_db(peak.load(Ordering::Relaxed))),
+                Some(Duration::from_millis(PEAK_METER_HOLD_MS)),
+            );
+            Label::new(
+                cx,
+                Data::gr_meter.map(|gain_reduction| {
+                    format!(
+                        "Gain reduction: {:.1} dB",
+                        gain_reduction.load(Ordering::Relaxed)
+                    )
+                }),
+            )
+            .top(Pixels(10.0));
+        })
+        .row_between(Pixels(0.0))
+        .child_left(Stretch(1.0))
+        .child_right(Stretch(1.0));
+
+        ResizeHandle::new(cx);
+    })
+}
+
+/// Keep applying the thresholds suggested by `learn` while the editor is open. The audio thread
+/// can't set parameters itself, see `PluginParams::apply_learned_threshold()`.
+fn poll_learned_threshold(
+    cx: &mut Context,
+    params: Arc<PluginParams>,
+    context: Arc<dyn GuiContext>,
+) {
+    let timer = cx.add_timer(
+        Duration::from_millis(LEARN_POLL_INTERVAL_MS),
+        None,
+        move |_, action| {
+            if let TimerAction::Tick(_) = action {
+                params.apply_learned_threshold(&ParamSetter::new(context.as_ref()));
+            }
+        },
+    );
+    cx.start_timer(timer);
+}
+
+/// Plots the clip stage's transfer function for the current parameter values, with the input and
+/// output both spanning `±TRANSFER_CURVE_RANGE`.
+struct TransferCurve {
+    params: Arc<PluginParams>,
+}
+
+impl TransferCurve {
+    const NUM_POINTS: usize = 128;
+
+    fn new(cx: &mut Context, params: Arc<PluginParams>) -> Handle<'_, Self> {
+        Self { params }.build(cx, |_| {})
+    }
+}
+
+impl View for TransferCurve {
+    fn element(&self) -> Option<&'static str> {
+        Some("transfer-curve")
+    }
+
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+        if bounds.w == 0.0 || bounds.h == 0.0 {
+            return;
+        }
+
+        let line_width = cx.scale_factor() * 1.5;
+        let to_x =
+            |input: f32| bounds.left() + (input / TRANSFER_CURVE_RANGE * 0.5 + 0.5) * bounds.w;
+        let to_y = |output: f32| {
+            let normalized = (output / TRANSFER_CURVE_RANGE).clamp(-1.0, 1.0) * 0.5 + 0.5;
+            bounds.bottom() - normalized * bounds.h
+        };
+
+        // Unity gain for reference
+        let mut unity = vg::Path::new();
+        unity.move_to(bounds.left(), bounds.bottom());
+        unity.line_to(bounds.right(), bounds.top());
+        canvas.stroke_path(
+            &unity,
+            &vg::Paint::color(vg::Color::rgba(0x80, 0x80, 0x80, 0x80)).with_line_width(line_width),
+        );
+
+        let mut curve = vg::Path::new();
+        for (i, (input, output)) in self
+            .params
+            .transfer_curve(Self::NUM_POINTS)
+            .into_iter()
+            .enumerate()
+        {
+            if i == 0 {
+                curve.move_to(to_x(input), to_y(output));
+            } else {
+                curve.line_to(to_x(input), to_y(output));
+            }
+        }
+        canvas.stroke_path(
+            &curve,
+            &vg::Paint::color(vg::Color::rgb(0xf5, 0xa6, 0x23)).with_line_width(line_width),
+        );
+    }
+}
@@ -0,0 +1,2335 @@
+//! The complete signal chain, independent from nih-plug's buffers and parameter objects so it can
+//! be driven offline, from tests and benchmarks, or from other projects.
+//!
+//! `ClipEngineParams` holds plain values for everything the chain reads, and `ClipEngine` keeps
+//! all filter, smoothing, and meter state. The plugin copies its parameters into the engine before
+//! every block and reads the meters back afterwards.
+//!
+//! The signal flow for a single channel, with the meters on the right where they tap it:
+//!
+//! ```text
+//! host input (non-finite samples silenced) ------------------- input_peak, input_rms
+//!   -> mid/side encode and width
+//!   -> gain and drive
+//!   -> lookahead -> limiter -> tilt pre-emphasis -> pre HPF -> bias
+//!   -> clip stage, oversampled ------------------------------- clip_ratio, gain_reduction_db
+//!   -> tilt de-emphasis -> DC blocker -> tone
+//!   -> output gain and auto gain
+//!   -> mix or delta against the delayed dry input -> ceiling -> polarity -> bypass
+//!   -> mid/side decode -> sidechain listen -> dither ---------- output_peak, output_rms,
+//!                                                               true_peak, over
+//! ```
+//!
+//! The input meters thus read what the host passes in regardless of any gain, and the output
+//! meters read exactly what's handed back to it.
+//!
+//! Oversampling only brackets the clip stage. Everything inside it, from the two-stage saturation
+//! and the multiband crossover to the clip release, the hysteresis, and the brickwall clamp, runs
+//! between a single upsampler and downsampler, while the linear filters around it stay at the base
+//! rate where they're cheaper and don't create anything the extra bandwidth would be needed for.
+//! Below the threshold the clip stage leaves the signal alone, so the brackets only delay it apart
+//! from the filters' passband ripple and, with the minimum phase filters, their phase shift
+//! towards the top of the audio band.
+
+use nih_plug::prelude::*;
+use nih_plug::util::{db_to_gain, gain_to_db};
+use std::sync::Arc;
+
+use crate::PluginParams;
+use crate::clip::{
+    Adaa, ClipRelease, ClipSettings, HardShape, Hysteresis, TransparentClipper, apply_clip,
+    hard_clip, knee_from_db, knee_start, static_gain_reduction_db,
+};
+pub use crate::clip::{ClipCurve, ClipSide};
+use crate::crossover::{Crossover, CrossoverCoefficients};
+use crate::delay::DelayLine;
+use crate::denormal::ScopedFtz;
+use crate::dither::Dither;
+use crate::envelope::{EnvelopeCoefficients, EnvelopeFollower};
+use crate::filter::{BUTTERWORTH_Q, Biquad, BiquadCoefficients};
+use crate::learn::{LEARN_CAPTURE_MS, ThresholdLearner};
+use crate::limiter::{LIMITER_ATTACK_MS, Limiter};
+use crate::lookahead::Lookahead;
+use crate::meter::RmsWindow;
+use crate::modulation::{FALLBACK_TEMPO_BPM, ModRate, TempoLfo};
+use crate::morph;
+use crate::oversample::{
+    FirStage, MAX_UPSAMPLING_GAIN, MonoOversamplers, OsFilter, OsQuality, Oversampler,
+    StereoOversamplers, UPSAMPLING_MEMORY_SAMPLES,
+};
+use crate::smoothing::{ParamSmoother, SmoothingConfig, SmoothingMode};
+use crate::table::{SharedTransferTable, TransferTable};
+
+/// The window length for the RMS meters.
+const RMS_WINDOW_MS: f32 = 300.0;
+/// The number of 2x stages used for true peak metering, for 4x oversampling.
+const TRUE_PEAK_STAGES: usize = 2;
+/// How long it takes to crossfade between the processed and the dry signal when toggling bypass.
+const BYPASS_FADE_MS: f32 = 10.0;
+/// The upper limit for `ClipEngineParams::lookahead_ms`.
+pub const MAX_LOOKAHEAD_MS: f32 = 5.0;
+/// The gain going into the wavefolder at 100% `fold_amount`.
+pub const MAX_FOLD_DRIVE: f32 = 8.0;
+/// The upper limit for `ClipEngineParams::shape_order`. Past this the polynomial curve is all but
+/// a square wave.
+pub const MAX_SHAPE_ORDER: f32 = 16.0;
+/// The sine folder and the wavefolder alias far more than the clipping curves, so they always run
+/// at least at this rate regardless of `ClipEngineParams::oversampling`.
+const FOLD_MIN_OVERSAMPLING: Oversampling = Oversampling::X8;
+/// The release of the peak envelope the program dependent threshold compares against its slower
+/// `pd_time_ms` average.
+const PD_PEAK_RELEASE_MS: f32 = 10.0;
+/// How far the peak envelope needs to jump above its average for the program dependent threshold
+/// to drop by the full `pd_amount`. Halfway there the threshold is left where it is.
+const PD_TRANSIENT_DB: f32 = 6.0;
+/// How far `symmetry` at its extremes lowers the threshold for one polarity.
+const MAX_SYMMETRY_DB: f32 = 12.0;
+/// The share of the level pushed past the threshold that still comes out as loudness, for the
+/// automatic makeup gain. Clipping flattens the peaks but the body of the signal keeps getting
+/// louder, so this is somewhere between nothing and everything.
+const AUTO_GAIN_CLIPPED_LOUDNESS: f32 = 0.5;
+/// The automatic makeup gain never goes further than this in either direction.
+const MAX_AUTO_GAIN_DB: f32 = 24.0;
+/// Roughly how much quieter the tanh curve comes out than the hard clipper at the same threshold
+/// for a signal that's being clipped, which `character` makes up for.
+const CHARACTER_MAKEUP_DB: f32 = 1.5;
+/// The filter cutoffs glide to new values over this time on a logarithmic scale, independently
+/// from the smoothing parameters.
+const FREQUENCY_SMOOTHING_MS: f32 = 50.0;
+/// Once everything going into the clip stage has stayed below this level for `IDLE_AFTER_MS`, the
+/// oversampling filters are skipped until the signal comes back. At -120 dBFS the oversampling
+/// filters are indistinguishable from a plain delay.
+const IDLE_THRESHOLD: f32 = 1.0e-6;
+const IDLE_AFTER_MS: f32 = 100.0;
+/// Taken off the lowest ceiling `ClipEngine::clip_stage_passes_through()` works out for a block,
+/// to cover the rounding in the per-sample gains and ceilings it doesn't reproduce exactly.
+const PASS_THROUGH_MARGIN_DB: f32 = 0.1;
+/// The frequency `ClipEngineParams::tilt` pivots around, which the emphasis leaves alone.
+const TILT_PIVOT_HZ: f32 = 1_000.0;
+/// How long `Meters::over` stays lit after the output last touched the ceiling.
+const OVER_HOLD_MS: f32 = 500.0;
+/// Output samples within this distance of the ceiling count as overs, so dither and rounding
+/// don't hide them.
+const OVER_TOLERANCE_DB: f32 = -0.01;
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StereoMode {
+    #[name = "Left/Right"]
+    LeftRight,
+    /// Clips the sum and difference of a stereo pair instead of the individual channels.
+    #[name = "Mid/Side"]
+    MidSide,
+}
+
+/// What the delta output is the difference against.
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeltaMode {
+    /// `mix * (wet - dry)`, with the dry signal at its original level. This includes every level
+    /// change the plugin makes, so with any gain or drive it also contains the dry signal.
+    #[name = "Vs Input"]
+    VsInput,
+    /// `mix * (wet - dry * gain * output_gain)`, with the dry signal taken through the same gain
+    /// staging as the wet signal, including the drive and auto gain. This leaves only what the
+    /// clip stage and the filters after it change.
+    #[name = "Vs Gained"]
+    VsGained,
+}
+
+#[inline(always)]
+fn mid_side_encode(left: f32, right: f32) -> (f32, f32) {
+    ((left + right) * 0.5, (left - right) * 0.5)
+}
+
+#[inline(always)]
+fn mid_side_decode(mid: f32, side: f32) -> (f32, f32) {
+    (mid + side, mid - side)
+}
+
+/// The hard clipper's saturation with `character` taking it the rest of the way towards the tanh
+/// curve, from `saturation` as it is at zero to pure tanh at one.
+#[inline(always)]
+pub fn saturation_with_character(saturation: f32, character: f32) -> f32 {
+    saturation + character * (1.0 - saturation)
+}
+
+/// Turn the positive and negative thresholds into linear ceilings, with `symmetry` lowering the
+/// positive threshold by up to `MAX_SYMMETRY_DB` as it goes towards +1 and the negative threshold
+/// as it goes towards -1. At zero both sides are left alone.
+#[inline(always)]
+pub fn ceilings_with_symmetry(
+    threshold_pos_db: f32,
+    threshold_neg_db: f32,
+    symmetry: f32,
+) -> (f32, f32) {
+    (
+        db_to_gain(threshold_pos_db - symmetry.max(0.0) * MAX_SYMMETRY_DB),
+        db_to_gain(threshold_neg_db + symmetry.min(0.0) * MAX_SYMMETRY_DB),
+    )
+}
+
+/// A rough estimate of the gain that brings a full scale signal driven by `input_gain_db` into a
+/// clipper at `threshold_db` back to its original loudness. The gain raises the loudness one to
+/// one, and the clipper takes back all but `AUTO_GAIN_CLIPPED_LOUDNESS` of the level it removes.
+#[inline(always)]
+fn makeup_gain_db(input_gain_db: f32, threshold_db: f32) -> f32 {
+    let clipped_db = (input_gain_db - threshold_db).max(0.0);
+    let added_db = input_gain_db - clipped_db * (1.0 - AUTO_GAIN_CLIPPED_LOUDNESS);
+    (-added_db).clamp(-MAX_AUTO_GAIN_DB, MAX_AUTO_GAIN_DB)
+}
+
+/// The sidechain channel with the same index as the main channel `channel_idx`, or the last one
+/// for sidechains with fewer channels, at `sample_idx`. Missing and non-finite samples are silent.
+#[inline(always)]
+fn sidechain_sample<S: AsRef<[f32]>>(
+    sidechain: &[S],
+    channel_idx: usize,
+    sample_idx: usize,
+) -> f32 {
+    sidechain
+        .get(channel_idx)
+        .or(sidechain.last())
+        .and_then(|channel| channel.as_ref().get(sample_idx))
+        .copied()
+        .filter(|sample| sample.is_finite())
+        .unwrap_or(0.0)
+}
+
+/// Replace the first two channels' samples at `sample_idx` with the result of `f`.
+#[inline(always)]
+fn map_stereo_pair(
+    channels: &mut [&mut [f32]],
+    sample_idx: usize,
+    f: impl FnOnce(f32, f32) -> (f32, f32),
+) {
+    if let [left, right, ..] = channels {
+        let (new_left, new_right) = f(left[sample_idx], right[sample_idx]);
+        left[sample_idx] = new_left;
+        right[sample_idx] = new_right;
+    }
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PreFilterMode {
+    /// The clip stage only sees the high-passed signal, so the lows are removed entirely.
+    #[name = "Filter Audio"]
+    Audio,
+    /// The clip stage's gain is computed from the high-passed signal and then applied to the
+    /// full-band signal. The lows stay in place but no longer decide when clipping starts.
+    #[name = "Filter Detection"]
+    Detection,
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Oversampling {
+    Off,
+    #[name = "2x"]
+    X2,
+    #[name = "4x"]
+    X4,
+    #[name = "8x"]
+    X8,
+}
+
+impl Oversampling {
+    /// The number of cascaded 2x stages needed for this factor.
+    fn stages(self) -> usize {
+        match self {
+            Oversampling::Off => 0,
+            Oversampling::X2 => 1,
+            Oversampling::X4 => 2,
+            Oversampling::X8 => 3,
+        }
+    }
+}
+
+/// Plain values for everything `ClipEngine` reads, see `PluginParams` for what each of these
+/// does. Levels are in decibels, times in milliseconds, frequencies in hertz, and percentages are
+/// fractions between zero and one. The defaults match the plugin's.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipEngineParams {
+    pub curve: ClipCurve,
+    pub clip_side: ClipSide,
+    pub stereo_mode: StereoMode,
+    pub width: f32,
+    pub oversampling: Oversampling,
+    pub os_quality: OsQuality,
+    pub os_filter: OsFilter,
+
+    pub gain: f32,
+    pub drive: f32,
+    pub threshold: f32,
+    pub knee: f32,
+    pub saturation: f32,
+    pub character: f32,
+    pub two_stage: bool,
+    pub sat_drive: f32,
+    pub link_thresholds: bool,
+    pub link_gain_threshold: bool,
+    pub threshold_pos: f32,
+    pub threshold_neg: f32,
+    pub symmetry: f32,
+    pub link_channels: bool,
+    pub stereo_link: bool,
+    pub link_amount: f32,
+    pub threshold_r: f32,
+
+    pub sidechain_enable: bool,
+    pub sidechain_depth: f32,
+    pub sc_attack_ms: f32,
+    pub sc_release_ms: f32,
+    pub sc_listen: bool,
+
+    pub mod_enable: bool,
+    pub mod_rate: ModRate,
+    pub mod_depth: f32,
+
+    pub program_dependent: bool,
+    pub pd_amount: f32,
+    pub pd_time_ms: f32,
+
+    pub pre_hpf_enable: bool,
+    pub pre_hpf_hz: f32,
+    pub pre_hpf_mode: PreFilterMode,
+    pub tilt: f32,
+    pub multiband_enable: bool,
+    pub crossover_lo_hz: f32,
+    pub crossover_hi_hz: f32,
+    pub threshold_low: f32,
+    pub threshold_mid: f32,
+    pub threshold_high: f32,
+
+    pub bias: f32,
+    pub fold_amount: f32,
+    pub shape_order: f32,
+    pub analog_mode: bool,
+    pub transparent_attack_ms: f32,
+    pub transparent_release_ms: f32,
+    pub clip_release_ms: f32,
+    pub lookahead_ms: f32,
+    pub limiter_enable: bool,
+    pub limiter_release_ms: f32,
+
+    pub output_gain: f32,
+    pub auto_gain: bool,
+    pub mix: f32,
+    pub ceiling_enable: bool,
+    pub ceiling_db: f32,
+    pub delta: bool,
+    pub delta_gain: f32,
+    pub delta_mode: DeltaMode,
+    pub phase_invert: bool,
+    pub dc_block: bool,
+    pub tone_enable: bool,
+    pub tone_hz: f32,
+    pub dither_enable: bool,
+    pub f64_filters: bool,
+    pub dither_bits: i32,
+
+    pub smoothing_mode: SmoothingMode,
+    pub smoothing_ms: f32,
+    pub meter_decay_ms: f32,
+    pub peak_hold: bool,
+    pub clip_ratio_oversampled: bool,
+    pub learn: bool,
+    pub bypass: bool,
+
+    /// Output the difference between the processed signal and the latency compensated dry input
+    /// with the gain staging forced to unity, so anything the clip stage leaves untouched nulls
+    /// to silence. The filters still follow their parameters, so the DC blocker and the tone
+    /// filter need to be disabled for a perfect null. This is not a plugin parameter, it only
+    /// exists for verifying the engine's transparency.
+    pub null_test: bool,
+}
+
+impl ClipEngineParams {
+    /// The parameters the engine actually runs with, with the folding curves raising the
+    /// oversampling to `FOLD_MIN_OVERSAMPLING` and `null_test` overriding everything that changes
+    /// the level of the processed signal.
+    fn resolve(self) -> Self {
+        let oversampling = if matches!(self.curve, ClipCurve::SineFold | ClipCurve::Wavefold)
+            && self.oversampling.stages() < FOLD_MIN_OVERSAMPLING.stages()
+        {
+            FOLD_MIN_OVERSAMPLING
+        } else {
+            self.oversampling
+        };
+        if !self.null_test {
+            return Self {
+                oversampling,
+                ..self
+            };
+        }
+
+        Self {
+            oversampling,
+            gain: 0.0,
+            drive: 0.0,
+            output_gain: 0.0,
+            auto_gain: false,
+            character: 0.0,
+            mix: 1.0,
+            delta: true,
+            phase_invert: false,
+            ceiling_enable: false,
+            dither_enable: false,
+            bypass: false,
+            ..self
+        }
+    }
+}
+
+impl Default for ClipEngineParams {
+    fn default() -> Self {
+        Self::from(&PluginParams::default())
+    }
+}
+
+impl From<&PluginParams> for ClipEngineParams {
+    fn from(params: &PluginParams) -> Self {
+        let [
+            drive,
+            threshold,
+            knee,
+            saturation,
+            symmetry,
+            bias,
+            fold_amount,
+        ] = if params.morph_enable.value() {
+            morph::blend(
+                params.morph_targets(),
+                &params.morph_a,
+                &params.morph_b,
+                params.morph.value(),
+            )
+        } else {
+            params.morph_targets().map(|target| target.value())
+        };
+
+        Self {
+            curve: params.curve.value(),
+            clip_side: params.clip_side.value(),
+            stereo_mode: params.stereo_mode.value(),
+            width: params.width.value(),
+            oversampling: params.oversampling.value(),
+            os_quality: params.os_quality.value(),
+            os_filter: params.os_filter.value(),
+
+            gain: params.gain.value(),
+            drive,
+            threshold,
+            knee,
+            saturation,
+            character: params.character.value(),
+            two_stage: params.two_stage.value(),
+            sat_drive: params.sat_drive.value(),
+            link_thresholds: params.link_thresholds.value(),
+            link_gain_threshold: params.link_gain_threshold.value(),
+            threshold_pos: params.threshold_pos.value(),
+            threshold_neg: params.threshold_neg.value(),
+            symmetry,
+            link_channels: params.link_channels.value(),
+            stereo_link: params.stereo_link.value(),
+            link_amount: params.link_amount.value(),
+            threshold_r: params.threshold_r.value(),
+
+            sidechain_enable: params.sidechain_enable.value(),
+            sidechain_depth: params.sidechain_depth.value(),
+            sc_attack_ms: params.sc_attack_ms.value(),
+            sc_release_ms: params.sc_release_ms.value(),
+            sc_listen: params.sc_listen.value(),
+
+            mod_enable: params.mod_enable.value(),
+            mod_rate: params.mod_rate.value(),
+            mod_depth: params.mod_depth.value(),
+
+            program_dependent: params.program_dependent.value(),
+            pd_amount: params.pd_amount.value(),
+            pd_time_ms: params.pd_time_ms.value(),
+
+            pre_hpf_enable: params.pre_hpf_enable.value(),
+            pre_hpf_hz: params.pre_hpf_hz.value(),
+            pre_hpf_mode: params.pre_hpf_mode.value(),
+            tilt: params.tilt.value(),
+            multiband_enable: params.multiband_enable.value(),
+            crossover_lo_hz: params.crossover_lo_hz.value(),
+            crossover_hi_hz: params.crossover_hi_hz.value(),
+            threshold_low: params.threshold_low.value(),
+            threshold_mid: params.threshold_mid.value(),
+            threshold_high: params.threshold_high.value(),
+
+            bias,
+            fold_amount,
+            shape_order: params.shape_order.value(),
+            analog_mode: params.analog_mode.value(),
+            transparent_attack_ms: params.transparent_attack_ms.value(),
+            transparent_release_ms: params.transparent_release_ms.value(),
+            clip_release_ms: params.clip_release_ms.value(),
+            lookahead_ms: params.lookahead_ms.value(),
+            limiter_enable: params.limiter_enable.value(),
+            limiter_release_ms: params.limiter_release_ms.value(),
+
+            output_gain: params.output_gain.value(),
+            auto_gain: params.auto_gain.value(),
+            mix: params.mix.value(),
+            ceiling_enable: params.ceiling_enable.value(),
+            ceiling_db: params.ceiling_db.value(),
+            delta: params.delta.value(),
+            delta_gain: params.delta_gain.value(),
+            delta_mode: params.delta_mode.value(),
+            phase_invert: params.phase_invert.value(),
+            dc_block: params.dc_block.value(),
+            tone_enable: params.tone_enable.value(),
+            tone_hz: params.tone_hz.value(),
+            dither_enable: params.dither_enable.value(),
+            dither_bits: params.dither_bits.value(),
+            f64_filters: params.f64_filters.value(),
+
+            smoothing_mode: params.smoothing_mode.value(),
+            smoothing_ms: params.smoothing_ms.value(),
+            meter_decay_ms: params.meter_decay_ms.value(),
+            peak_hold: params.peak_hold.value(),
+            clip_ratio_oversampled: params.clip_ratio_oversampled.value(),
+            learn: params.learn.value(),
+            bypass: params.bypass.value(),
+
+            null_test: false,
+        }
+    }
+}
+
+/// The meter readings after the last processed block. Peak and RMS levels are linear and taken
+/// across all channels, with the RMS power averaged over the channels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Meters {
+    /// Sample peak levels before and after processing, with the meter ballistics already applied.
+    /// These are taken before the gain and after the output gain, see the module documentation.
+    /// With `peak_hold` enabled these and `true_peak` never decay.
+    pub input_peak: f32,
+    pub output_peak: f32,
+    /// RMS levels before and after processing over the last `RMS_WINDOW_MS`.
+    pub input_rms: f32,
+    pub output_rms: f32,
+    /// The output's true peak level, measured by interpolating the output to four times the
+    /// sample rate. This uses the same release as the peak meters.
+    pub true_peak: f32,
+    /// How much the lookahead, the limiter, and the clip stage are taking off the driven signal,
+    /// in positive decibels. Where possible this follows the curve's static shape, see
+    /// `static_gain_reduction_db()`, so it rises smoothly through the knee. This uses the same
+    /// release as the peak meters, but keeps decaying while they're held.
+    pub gain_reduction_db: f32,
+    /// The fraction of samples over the last `RMS_WINDOW_MS`, across all channels, that went into
+    /// the clip stage above the threshold. Like the RMS levels this is measured over a fixed
+    /// number of samples, so it doesn't depend on how the host splits up its blocks.
+    ///
+    /// With `clip_ratio_oversampled` enabled and the oversampling active, this instead counts the
+    /// clip stage's samples at the oversampled rate. Those include the peaks the interpolation
+    /// reconstructs between the original samples, so a signal whose samples all stay just under
+    /// the threshold can still read above zero here, and the ratio is usually somewhat higher
+    /// than at the base rate. The difference between the two is the inter-sample clipping.
+    pub clip_ratio: f32,
+    /// The correlation coefficient between the left and right output over the last
+    /// `RMS_WINDOW_MS`, from 1 for a mono signal through 0 for unrelated channels to -1 for a
+    /// signal that cancels out when summed to mono. Layouts with other channel counts and
+    /// silence report 1.
+    pub correlation: f32,
+    /// Whether the output reached the ceiling, or full scale with the ceiling disabled, within the
+    /// last `OVER_HOLD_MS`. This is meant for an overload indicator.
+    pub over: bool,
+    /// The number of NaN and infinite input samples that were replaced with silence since the
+    /// last reset.
+    pub non_finite_samples: u32,
+}
+
+impl Meters {
+    /// The readings for silence, which are all zero apart from the correlation.
+    pub fn silent() -> Self {
+        Self {
+            input_peak: 0.0,
+            output_peak: 0.0,
+            input_rms: 0.0,
+            output_rms: 0.0,
+            true_peak: 0.0,
+            gain_reduction_db: 0.0,
+            clip_ratio: 0.0,
+            correlation: 1.0,
+            over: false,
+            non_finite_samples: 0,
+        }
+    }
+}
+
+/// The correlation coefficient for the mean product of two signals and their mean squares. Below
+/// this power the signals are treated as silence, which is perfectly mono compatible.
+#[inline]
+fn correlation(mean_product: f32, mean_square_left: f32, mean_square_right: f32) -> f32 {
+    let power = (mean_square_left * mean_square_right).sqrt();
+    if power > 1.0e-12 {
+        (mean_product / power).clamp(-1.0, 1.0)
+    } else {
+        1.0
+    }
+}
+
+/// First-order DC blocking high-pass, `y[n] = x[n] - x[n-1] + R * y[n-1]`. Like `Biquad` this
+/// keeps its state in double precision, and the f64 filter mode also does the math in it.
+#[derive(Clone, Copy, Default)]
+struct DcBlocker {
+    x1: f64,
+    y1: f64,
+}
+
+impl DcBlocker {
+    const R: f64 = 0.995;
+
+    #[inline(always)]
+    fn process(&mut self, x: f32, double_precision: bool) -> f32 {
+        if double_precision {
+            let x = x as f64;
+            let y = x - self.x1 + Self::R * self.y1;
+            self.x1 = x;
+            self.y1 = y;
+            y as f32
+        } else {
+            let y = x - self.x1 as f32 + Self::R as f32 * self.y1 as f32;
+            self.x1 = x as f64;
+            self.y1 = y as f64;
+            y
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// The clip stage's ceilings for a single channel and sample frame.
+#[derive(Clone, Copy, Default)]
+struct Ceilings {
+    pos: f32,
+    neg: f32,
+    /// The low, mid, and high bands' ceilings in multiband mode.
+    bands: [f32; 3],
+}
+
+impl Ceilings {
+    /// Whether `signal` lies above the ceiling for its polarity, for `Meters::clip_ratio`.
+    #[inline]
+    fn exceeded_by(&self, signal: f32) -> bool {
+        let ceiling = if signal >= 0.0 { self.pos } else { self.neg };
+        signal.abs() > ceiling
+    }
+}
+
+/// Everything about the clip stage that's shared between all channels for a sample frame.
+struct ClipStage<'a> {
+    settings: ClipSettings<'a>,
+    multiband: bool,
+    crossover_coefficients: &'a CrossoverCoefficients,
+    /// The brickwall ceiling, for clamping the clip stage's output at the oversampled rate.
+    oversampled_ceiling: Option<f32>,
+    bias: f32,
+    /// Whether the output goes through `Hysteresis`, and its coefficient at the clip stage's rate.
+    analog_mode: bool,
+    hysteresis_coefficient: f32,
+    /// `ClipRelease`'s coefficient at the clip stage's rate.
+    release_coefficient: f32,
+    /// The transparent curve's envelope at the clip stage's rate.
+    transparent_coefficients: EnvelopeCoefficients,
+    /// How much stereo pairs share their gain reduction, see `link_gain_reduction()`. This is zero
+    /// while the stereo link is disabled.
+    link_amount: f32,
+    /// Whether nothing in the current block can reach the knee, so the curve can be skipped. See
+    /// `ClipEngine::clip_stage_passes_through()`.
+    pass_through: bool,
+}
+
+impl ClipStage<'_> {
+    /// Apply the brickwall ceiling to the clip stage's output at the oversampled rate, when it's
+    /// enabled.
+    #[inline]
+    fn clamp_oversampled(&self, clipped: f32) -> f32 {
+        match self.oversampled_ceiling {
+            Some(ceiling) => hard_clip(clipped - self.bias, ceiling) + self.bias,
+            None => clipped,
+        }
+    }
+}
+
+/// The program dependent threshold's offset in decibels for the input's peak envelope and its
+/// average: up to `amount_db` higher while the two match during sustained passages, and up to
+/// `amount_db` lower once the peaks jump `PD_TRANSIENT_DB` or more above their average.
+#[inline]
+fn program_dependent_offset_db(peak: f32, average: f32, amount_db: f32) -> f32 {
+    if amount_db <= 0.0 {
+        return 0.0;
+    }
+
+    // Silence counts as sustained, and the onset after it as a transient
+    let crest_db = gain_to_db(peak / average.max(1.0e-6)).max(0.0);
+    amount_db * (1.0 - 2.0 * (crest_db / PD_TRANSIENT_DB).min(1.0))
+}
+
+/// The gain the clip stage applied to `signal` to get `clipped`.
+#[inline(always)]
+fn clip_gain(signal: f32, clipped: f32) -> f32 {
+    if signal.abs() > 1.0e-9 {
+        clipped / signal
+    } else {
+        1.0
+    }
+}
+
+/// Scale both signals of a stereo pair by whichever gain the clip stage applied to them is lower,
+/// so clipping only one channel doesn't pull the stereo image towards the other. This keeps the
+/// image intact at the cost of clipping the quieter channel harder than it needs to be, and it
+/// turns the curve into plain gain reduction, which gives the folding curves a very different
+/// character. Both the signals and the clipped results include the bias. `amount` blends from the
+/// clip stage's own output at zero to fully linked at one.
+#[inline(always)]
+fn link_gain_reduction(signals: [f32; 2], clipped: [f32; 2], bias: f32, amount: f32) -> [f32; 2] {
+    let [left_gain, right_gain] =
+        [0, 1].map(|channel| clip_gain(signals[channel] - bias, clipped[channel] - bias));
+    let gain = left_gain.min(right_gain);
+    // Blending the outputs instead of the gains keeps the curve's own output intact at zero, even
+    // for samples too close to zero for `clip_gain()`
+    [0, 1].map(|channel| {
+        let linked = (signals[channel] - bias) * gain + bias;
+        clipped[channel] + amount * (linked - clipped[channel])
+    })
+}
+
+/// Move both channels' gains towards the lower of the two by `amount`, from independent at zero to
+/// fully linked at one.
+#[inline(always)]
+fn link_gains([left_gain, right_gain]: [f32; 2], amount: f32) -> [f32; 2] {
+    let linked = left_gain.min(right_gain);
+    [left_gain, right_gain].map(|gain| gain + amount * (linked - gain))
+}
+
+/// The clip stage's per-channel state.
+#[derive(Default)]
+struct ClipState {
+    adaa: Adaa,
+    transparent: TransparentClipper,
+    crossover: Crossover,
+    /// The antialiasing and transparent curve state for the low, mid, and high bands in multiband
+    /// mode.
+    band_adaa: [Adaa; 3],
+    band_transparent: [TransparentClipper; 3],
+    release: ClipRelease,
+    hysteresis: Hysteresis,
+    /// How many of the samples passed to `clip()` since the last `take_over_ratio()` call were
+    /// above the threshold, and how many there were in total. At the oversampled rate this
+    /// includes the interpolated samples in between.
+    num_over: u32,
+    num_samples: u32,
+}
+
+impl ClipState {
+    /// Run `signal` through the selected curve, or through one instance per band in multiband
+    /// mode, followed by the clip release and the hysteresis in analog mode.
+    #[inline]
+    fn clip(&mut self, stage: &ClipStage, ceilings: &Ceilings, signal: f32) -> f32 {
+        let settings = &stage.settings;
+        self.num_over += ceilings.exceeded_by(signal) as u32;
+        self.num_samples += 1;
+        // The two-stage clipper's saturation feeds the whole clip stage below, including all
+        // three bands in multiband mode
+        let signal = settings.presaturate(signal, ceilings.pos, ceilings.neg);
+        let clip_with = |adaa: &mut Adaa,
+                         transparent: &mut TransparentClipper,
+                         signal,
+                         ceiling_pos,
+                         ceiling_neg| match settings.curve {
+            ClipCurve::HardAdaa => {
+                let (ceiling_pos, ceiling_neg) = settings.side.ceilings(ceiling_pos, ceiling_neg);
+                let shape = HardShape {
+                    knee: settings.knee,
+                };
+                // The averaging still needs to see the untouched side's samples, but its output
+                // for them would be smeared across the segment from the previous sample
+                let clipped = adaa.process(&shape, signal, ceiling_pos, ceiling_neg);
+                if settings.side.clips(signal) {
+                    settings.saturate(clipped, signal, ceiling_pos, ceiling_neg)
+                } else {
+                    signal
+                }
+            }
+            ClipCurve::Transparent => {
+                let (ceiling_pos, ceiling_neg) = settings.side.ceilings(ceiling_pos, ceiling_neg);
+                transparent.process(
+                    signal,
+                    ceiling_pos,
+                    ceiling_neg,
+                    &stage.transparent_coefficients,
+                )
+            }
+            _ => apply_clip(settings, signal, ceiling_pos, ceiling_neg),
+        };
+
+        // Below the knee the output is bit-identical either way. The release and the hysteresis
+        // below still see the sample so their state matches the full path.
+        let clipped = if stage.pass_through {
+            signal
+        } else if stage.multiband {
+            let bands = self.crossover.split(stage.crossover_coefficients, signal);
+            bands
+                .into_iter()
+                .zip(ceilings.bands)
+                .zip(self.band_adaa.iter_mut().zip(&mut self.band_transparent))
+                .map(|((band, ceiling), (adaa, transparent))| {
+                    clip_with(adaa, transparent, band, ceiling, ceiling)
+                })
+                .sum()
+        } else {
+            clip_with(
+                &mut self.adaa,
+                &mut self.transparent,
+                signal,
+                ceilings.pos,
+                ceilings.neg,
+            )
+        };
+
+        let clipped = self
+            .release
+            .process(signal, clipped, stage.release_coefficient);
+
+        // This keeps running outside of analog mode so enabling it doesn't start from stale state
+        let analog = self
+            .hysteresis
+            .process(signal, clipped, stage.hysteresis_coefficient);
+        if stage.analog_mode { analog } else { clipped }
+    }
+
+    /// The fraction of the samples passed to `clip()` since the last call that were above the
+    /// threshold, or `None` if there weren't any.
+    fn take_over_ratio(&mut self) -> Option<f32> {
+        let ratio = (self.num_samples > 0).then(|| self.num_over as f32 / self.num_samples as f32);
+        self.num_over = 0;
+        self.num_samples = 0;
+        ratio
+    }
+
+    fn reset(&mut self) {
+        self.adaa.reset();
+        self.transparent.reset();
+        self.crossover.reset();
+        for adaa in &mut self.band_adaa {
+            adaa.reset();
+        }
+        for transparent in &mut self.band_transparent {
+            transparent.reset();
+        }
+        self.release.reset();
+        self.hysteresis.reset();
+        self.num_over = 0;
+        self.num_samples = 0;
+    }
+}
+
+/// A channel's intermediate results for a single sample frame, passed between the stages of
+/// `ClipEngine::process_block_with_sidechain()`.
+#[derive(Clone, Copy, Default)]
+struct ChannelFrame {
+    /// The input, delayed to line up with the processed signal.
+    delayed_dry: f32,
+    /// The input with the input gain and drive, delayed like `delayed_dry`, for the gain reduction
+    /// meter and the delta against the gained input.
+    driven: f32,
+    /// Whether the clip stage's input, or the detection filter's output, is above the threshold
+    /// at the base sample rate.
+    exceeds_threshold: bool,
+    clipper_input: f32,
+    /// The filtered signal in the detection filter mode.
+    detector: Option<f32>,
+    /// The gain the detection filter mode applies to `clipper_input`.
+    detector_gain: f32,
+    ceilings: Ceilings,
+    /// The clip stage's output with the bias removed again.
+    clipped: f32,
+}
+
+/// Filter and delay state for a single channel.
+struct ChannelState {
+    /// Delays the dry signal by the same amount as the processed signal.
+    dry_delay: DelayLine,
+    /// Delays the input with the gain applied along with the dry signal, so gain changes line up
+    /// with the processed signal as well.
+    driven_delay: DelayLine,
+    lookahead: Lookahead,
+    limiter: Limiter,
+    pre_hpf: Biquad,
+    /// The pre-emphasis in front of the clip stage and the matching de-emphasis behind it.
+    tilt_pre: Biquad,
+    tilt_post: Biquad,
+    /// Delays the detection filter mode's output by the oversampling latency, since that path
+    /// doesn't go through the oversampler.
+    detection_delay: DelayLine,
+    clipper: ClipState,
+    dc_blocker: DcBlocker,
+    tone_lpf: Biquad,
+    /// Follows the sidechain channel with the same index, or the last one for sidechains with
+    /// fewer channels.
+    sidechain_follower: EnvelopeFollower,
+    /// The input's peak envelope and its slower average, for the program dependent threshold.
+    pd_peak: EnvelopeFollower,
+    pd_average: EnvelopeFollower,
+    dither: Dither,
+    oversamplers: MonoOversamplers,
+    input_rms: RmsWindow,
+    output_rms: RmsWindow,
+    /// Interpolates the output for the true peak meter. This is separate from `oversamplers` since
+    /// it measures the final output, and its latency doesn't matter since it's only used for
+    /// metering.
+    true_peak_upsampler: Oversampler<FirStage>,
+    /// The intermediate results for the sample frame that's currently being processed.
+    frame: ChannelFrame,
+}
+
+impl ChannelState {
+    /// `max_oversampling_latency` is the longest delay the oversampling filters can add.
+    fn new(
+        max_lookahead_samples: usize,
+        max_oversampling_latency: usize,
+        rms_window_samples: usize,
+        dither_seed: u32,
+    ) -> Self {
+        Self {
+            dry_delay: DelayLine::new(max_lookahead_samples + max_oversampling_latency),
+            driven_delay: DelayLine::new(max_lookahead_samples + max_oversampling_latency),
+            lookahead: Lookahead::new(max_lookahead_samples),
+            limiter: Limiter::default(),
+            pre_hpf: Biquad::default(),
+            tilt_pre: Biquad::default(),
+            tilt_post: Biquad::default(),
+            detection_delay: DelayLine::new(max_oversampling_latency),
+            clipper: ClipState::default(),
+            dc_blocker: DcBlocker::default(),
+            tone_lpf: Biquad::default(),
+            sidechain_follower: EnvelopeFollower::default(),
+            pd_peak: EnvelopeFollower::default(),
+            pd_average: EnvelopeFollower::default(),
+            dither: Dither::new(dither_seed),
+            oversamplers: MonoOversamplers::default(),
+            input_rms: RmsWindow::new(rms_window_samples),
+            output_rms: RmsWindow::new(rms_window_samples),
+            true_peak_upsampler: Oversampler::new(OsQuality::Normal),
+            frame: ChannelFrame::default(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.dry_delay.reset();
+        self.driven_delay.reset();
+        self.lookahead.reset();
+        self.limiter.reset();
+        self.pre_hpf.reset();
+        self.tilt_pre.reset();
+        self.tilt_post.reset();
+        self.detection_delay.reset();
+        self.clipper.reset();
+        self.dc_blocker.reset();
+        self.tone_lpf.reset();
+        self.sidechain_follower.reset();
+        self.pd_peak.reset();
+        self.pd_average.reset();
+        self.oversamplers.reset();
+        self.input_rms.reset();
+        self.output_rms.reset();
+        self.true_peak_upsampler.reset();
+    }
+}
+
+/// The smoothed parameters, see the `smoothing` module. The filter cutoffs are smoothed on a
+/// logarithmic scale over `FREQUENCY_SMOOTHING_MS` instead of following the smoothing parameters.
+///
+/// All smoothers advance exactly once per sample frame through `ParamSmoothers::next()`, and
+/// every channel in that frame sees the same value. A smoothing time thus covers the same number
+/// of frames regardless of the channel count. With sample accurate automation the host's buffer
+/// is split at every parameter change, so new targets take effect on the frame they were
+/// automated at.
+#[derive(Default)]
+struct ParamSmoothers {
+    width: ParamSmoother,
+    gain: ParamSmoother,
+    drive: ParamSmoother,
+    threshold: ParamSmoother,
+    threshold_pos: ParamSmoother,
+    threshold_neg: ParamSmoother,
+    threshold_r: ParamSmoother,
+    knee: ParamSmoother,
+    saturation: ParamSmoother,
+    character: ParamSmoother,
+    /// Fades the two-stage clipper's saturation in and out, like `auto_gain`.
+    two_stage: ParamSmoother,
+    sat_drive: ParamSmoother,
+    link_amount: ParamSmoother,
+    sidechain_depth: ParamSmoother,
+    /// Fades the limiter in and out, like `auto_gain`.
+    limiter: ParamSmoother,
+    tilt: ParamSmoother,
+    /// Fades out with the LFO when it gets disabled.
+    mod_depth: ParamSmoother,
+    /// The same for the program dependent threshold.
+    pd_amount: ParamSmoother,
+    symmetry: ParamSmoother,
+    threshold_low: ParamSmoother,
+    threshold_mid: ParamSmoother,
+    threshold_high: ParamSmoother,
+    bias: ParamSmoother,
+    fold_amount: ParamSmoother,
+    shape_order: ParamSmoother,
+    output_gain: ParamSmoother,
+    mix: ParamSmoother,
+    ceiling_db: ParamSmoother,
+    delta_gain: ParamSmoother,
+    /// Fades the automatic makeup gain in and out, from zero when it's off to one when it's on.
+    auto_gain: ParamSmoother,
+    /// Fades the gain's influence on the thresholds in and out, like `auto_gain`.
+    link_gain_threshold: ParamSmoother,
+    /// Fades the output's polarity between one and minus one, passing through silence.
+    polarity: ParamSmoother,
+    /// These smooth the base two logarithm of the frequency.
+    pre_hpf_hz: ParamSmoother,
+    crossover_lo_hz: ParamSmoother,
+    crossover_hi_hz: ParamSmoother,
+    tone_hz: ParamSmoother,
+}
+
+fn mod_depth(params: &ClipEngineParams) -> f32 {
+    if params.mod_enable {
+        params.mod_depth
+    } else {
+        0.0
+    }
+}
+
+fn pd_amount(params: &ClipEngineParams) -> f32 {
+    if params.program_dependent {
+        params.pd_amount
+    } else {
+        0.0
+    }
+}
+
+fn two_stage_amount(params: &ClipEngineParams) -> f32 {
+    if params.two_stage { 1.0 } else { 0.0 }
+}
+
+fn limiter_amount(params: &ClipEngineParams) -> f32 {
+    if params.limiter_enable { 1.0 } else { 0.0 }
+}
+
+fn auto_gain_amount(params: &ClipEngineParams) -> f32 {
+    if params.auto_gain { 1.0 } else { 0.0 }
+}
+
+fn link_gain_threshold_amount(params: &ClipEngineParams) -> f32 {
+    if params.link_gain_threshold { 1.0 } else { 0.0 }
+}
+
+fn polarity(params: &ClipEngineParams) -> f32 {
+    if params.phase_invert { -1.0 } else { 1.0 }
+}
+
+/// The values from `ParamSmoothers` for a single sample frame.
+#[derive(Clone, Copy)]
+struct SmoothedValues {
+    width: f32,
+    gain: f32,
+    drive: f32,
+    threshold: f32,
+    threshold_pos: f32,
+    threshold_neg: f32,
+    threshold_r: f32,
+    knee: f32,
+    saturation: f32,
+    character: f32,
+    two_stage: f32,
+    sat_drive: f32,
+    link_amount: f32,
+    sidechain_depth: f32,
+    limiter: f32,
+    tilt: f32,
+    mod_depth: f32,
+    pd_amount: f32,
+    symmetry: f32,
+    threshold_low: f32,
+    threshold_mid: f32,
+    threshold_high: f32,
+    bias: f32,
+    fold_amount: f32,
+    shape_order: f32,
+    output_gain: f32,
+    mix: f32,
+    ceiling_db: f32,
+    delta_gain: f32,
+    auto_gain: f32,
+    link_gain_threshold: f32,
+    polarity: f32,
+    pre_hpf_hz: f32,
+    crossover_lo_hz: f32,
+    crossover_hi_hz: f32,
+    tone_hz: f32,
+}
+
+impl ParamSmoothers {
+    /// Advance every smoother by one sample frame towards its parameter's current value. This
+    /// should be the only place any smoother gets advanced.
+    #[inline]
+    fn next(
+        &mut self,
+        params: &ClipEngineParams,
+        config: &SmoothingConfig,
+        frequency_config: &SmoothingConfig,
+    ) -> SmoothedValues {
+        let frequency = |smoother: &mut ParamSmoother, hz: f32| {
+            smoother.next(hz.log2(), frequency_config).exp2()
+        };
+
+        SmoothedValues {
+            width: self.width.next(params.width, config),
+            gain: self.gain.next(params.gain, config),
+            drive: self.drive.next(params.drive, config),
+            threshold: self.threshold.next(params.threshold, config),
+            threshold_pos: self.threshold_pos.next(params.threshold_pos, config),
+            threshold_neg: self.threshold_neg.next(params.threshold_neg, config),
+            threshold_r: self.threshold_r.next(params.threshold_r, config),
+            knee: self.knee.next(params.knee, config),
+            saturation: self.saturation.next(params.saturation, config),
+            character: self.character.next(params.character, config),
+            two_stage: self.two_stage.next(two_stage_amount(params), config),
+            sat_drive: self.sat_drive.next(params.sat_drive, config),
+            link_amount: self.link_amount.next(params.link_amount, config),
+            sidechain_depth: self.sidechain_depth.next(params.sidechain_depth, config),
+            limiter: self.limiter.next(limiter_amount(params), config),
+            tilt: self.tilt.next(params.tilt, config),
+            mod_depth: self.mod_depth.next(mod_depth(params), config),
+            pd_amount: self.pd_amount.next(pd_amount(params), config),
+            symmetry: self.symmetry.next(params.symmetry, config),
+            threshold_low: self.threshold_low.next(params.threshold_low, config),
+            threshold_mid: self.threshold_mid.next(params.threshold_mid, config),
+            threshold_high: self.threshold_high.next(params.threshold_high, config),
+            bias: self.bias.next(params.bias, config),
+            fold_amount: self.fold_amount.next(params.fold_amount, config),
+            shape_order: self.shape_order.next(params.shape_order, config),
+            output_gain: self.output_gain.next(params.output_gain, config),
+            mix: self.mix.next(params.mix, config),
+            ceiling_db: self.ceiling_db.next(params.ceiling_db, config),
+            delta_gain: self.delta_gain.next(params.delta_gain, config),
+            auto_gain: self.auto_gain.next(auto_gain_amount(params), config),
+            link_gain_threshold: self
+                .link_gain_threshold
+                .next(link_gain_threshold_amount(params), config),
+            polarity: self.polarity.next(polarity(params), config),
+            pre_hpf_hz: frequency(&mut self.pre_hpf_hz, params.pre_hpf_hz),
+            crossover_lo_hz: frequency(&mut self.crossover_lo_hz, params.crossover_lo_hz),
+            crossover_hi_hz: frequency(&mut self.crossover_hi_hz, params.crossover_hi_hz),
+            tone_hz: frequency(&mut self.tone_hz, params.tone_hz),
+        }
+    }
+
+    /// Snap every smoother to its parameter's current value.
+    fn reset(&mut self, params: &ClipEngineParams) {
+        self.width.reset(params.width);
+        self.gain.reset(params.gain);
+        self.drive.reset(params.drive);
+        self.threshold.reset(params.threshold);
+        self.threshold_pos.reset(params.threshold_pos);
+        self.threshold_neg.reset(params.threshold_neg);
+        self.threshold_r.reset(params.threshold_r);
+        self.knee.reset(params.knee);
+        self.saturation.reset(params.saturation);
+        self.character.reset(params.character);
+        self.two_stage.reset(two_stage_amount(params));
+        self.sat_drive.reset(params.sat_drive);
+        self.link_amount.reset(params.link_amount);
+        self.sidechain_depth.reset(params.sidechain_depth);
+        self.limiter.reset(limiter_amount(params));
+        self.tilt.reset(params.tilt);
+        self.mod_depth.reset(mod_depth(params));
+        self.pd_amount.reset(pd_amount(params));
+        self.symmetry.reset(params.symmetry);
+        self.threshold_low.reset(params.threshold_low);
+        self.threshold_mid.reset(params.threshold_mid);
+        self.threshold_high.reset(params.threshold_high);
+        self.bias.reset(params.bias);
+        self.fold_amount.reset(params.fold_amount);
+        self.shape_order.reset(params.shape_order);
+        self.output_gain.reset(params.output_gain);
+        self.mix.reset(params.mix);
+        self.ceiling_db.reset(params.ceiling_db);
+        self.delta_gain.reset(params.delta_gain);
+        self.auto_gain.reset(auto_gain_amount(params));
+        self.link_gain_threshold
+            .reset(link_gain_threshold_amount(params));
+        self.polarity.reset(polarity(params));
+        self.pre_hpf_hz.reset(params.pre_hpf_hz.log2());
+        self.crossover_lo_hz.reset(params.crossover_lo_hz.log2());
+        self.crossover_hi_hz.reset(params.crossover_hi_hz.log2());
+        self.tone_hz.reset(params.tone_hz.log2());
+    }
+}
+
+/// The clipper's signal chain for a fixed sample rate and channel count.
+pub struct ClipEngine {
+    params: ClipEngineParams,
+
+    sample_rate: f32,
+
+    /// One entry per channel. This only allocates in `ClipEngine::new()`.
+    channels: Vec<ChannelState>,
+    /// Used instead of the channels' own oversamplers for stereo.
+    stereo_oversamplers: StereoOversamplers,
+    smoothers: ParamSmoothers,
+
+    /// Modulates the threshold when `mod_enable` is set.
+    lfo: TempoLfo,
+    /// The host's tempo, if it reported one.
+    tempo_bpm: Option<f64>,
+    /// The host's position in beats at the start of the next block, if it reported one. This is
+    /// only used once.
+    position_beats: Option<f64>,
+
+    /// The oversampling factor, quality, and filter type the filter state was last set up for.
+    oversampling: Oversampling,
+    os_quality: OsQuality,
+    os_filter: OsFilter,
+
+    /// The cutoff `pre_hpf_coefficients` were last computed for, so they only need to be
+    /// recomputed while the parameter is changing.
+    pre_hpf_hz: f32,
+    pre_hpf_coefficients: BiquadCoefficients,
+    /// The same for the tilt's pre-emphasis and de-emphasis.
+    tilt: f32,
+    tilt_pre_coefficients: BiquadCoefficients,
+    tilt_post_coefficients: BiquadCoefficients,
+    /// The sample rate and the low and high crossover frequencies `crossover_coefficients` were
+    /// last computed for. The crossover runs at the oversampled rate, so this also changes with
+    /// the oversampling factor.
+    crossover_settings: (f32, f32, f32),
+    crossover_coefficients: CrossoverCoefficients,
+    /// Also depends on the clip stage's rate, so this is updated together with the crossover.
+    hysteresis_coefficient: f32,
+    /// The clip stage's rate and the attack and release times `transparent_coefficients` were
+    /// last computed for.
+    transparent_settings: (f32, f32, f32),
+    transparent_coefficients: EnvelopeCoefficients,
+    /// The same for the clip release.
+    clip_release_settings: (f32, f32),
+    clip_release_coefficient: f32,
+    /// The same for the post-clip tone filter.
+    tone_hz: f32,
+    tone_coefficients: BiquadCoefficients,
+
+    /// How far the output has faded towards the dry signal, from 0 (processed) to 1 (bypassed).
+    bypass_amount: f32,
+    /// The per-sample change in `bypass_amount` while fading, derived from the sample rate.
+    bypass_fade_step: f32,
+
+    meters: Meters,
+    /// Holds the fraction of every channel's samples over the last `RMS_WINDOW_MS` that went into
+    /// the clip stage above the threshold, so its mean is `Meters::clip_ratio`. At the base rate
+    /// that's always a one or a zero. This covers all channels, so it's that many times as long.
+    clip_window: RmsWindow,
+    /// The products of the left and right output samples over the last `RMS_WINDOW_MS`, which
+    /// together with the channels' output RMS windows gives `Meters::correlation`.
+    correlation_window: RmsWindow,
+    /// Whether the oversampling filters are being skipped because the clip stage's input has been
+    /// silent for `idle_after_samples`, and for how many frames it's been silent so far.
+    idle: bool,
+    silent_samples: usize,
+    idle_after_samples: usize,
+    /// For how many frames the input has been below `IDLE_THRESHOLD`, for `tail_samples()`.
+    silent_input_samples: usize,
+    /// The driven input's peak over two consecutive spans of at least `driven_peak_span()`
+    /// frames, the older one first, and how many frames the newer one covers so far. Between them
+    /// they cover everything that can still reach the clip stage through the lookahead and the
+    /// upsampling filters, for `clip_stage_passes_through()`.
+    driven_peaks: [f32; 2],
+    driven_peak_frames: usize,
+
+    /// The number of samples `Meters::over` stays lit for after the last over, and how many of
+    /// those are left.
+    over_hold_samples: usize,
+    over_hold_remaining: usize,
+
+    /// Whether the peak meters were held during the last block. The held peaks are cleared when
+    /// peak hold gets enabled.
+    peak_hold: bool,
+
+    /// Suggests a threshold while `learn` is enabled, from `learn_capture_samples` frames of the
+    /// clip stage's input.
+    learner: ThresholdLearner,
+    learn_capture_samples: usize,
+
+    /// The table curve's table, replaced at the start of a block whenever `shared_transfer_table`
+    /// has a new one.
+    transfer_table: Box<TransferTable>,
+    shared_transfer_table: Arc<SharedTransferTable>,
+}
+
+impl ClipEngine {
+    /// Allocate the state for `num_channels` channels at `sample_rate`, starting out with `params`
+    /// without any smoothing.
+    pub fn new(sample_rate: f32, num_channels: usize, params: ClipEngineParams) -> Self {
+        let max_lookahead_samples = (MAX_LOOKAHEAD_MS * sample_rate / 1000.0).ceil() as usize;
+        let rms_window_samples = (RMS_WINDOW_MS * sample_rate / 1000.0).round() as usize;
+        let params = params.resolve();
+        let stereo_oversamplers = StereoOversamplers::default();
+        let max_oversampling_latency = stereo_oversamplers.max_latency() as usize;
+
+        let mut engine = Self {
+            params,
+
+            sample_rate,
+
+            channels: (0..num_channels)
+                .map(|channel_idx| {
+                    ChannelState::new(
+                        max_lookahead_samples,
+                        max_oversampling_latency,
+                        rms_window_samples,
+                        channel_idx as u32,
+                    )
+                })
+                .collect(),
+            stereo_oversamplers,
+            smoothers: ParamSmoothers::default(),
+
+            lfo: TempoLfo::default(),
+            tempo_bpm: None,
+            position_beats: None,
+
+            oversampling: params.oversampling,
+            os_quality: params.os_quality,
+            os_filter: params.os_filter,
+
+            pre_hpf_hz: params.pre_hpf_hz,
+            pre_hpf_coefficients: BiquadCoefficients::highpass(
+                sample_rate,
+                params.pre_hpf_hz,
+                BUTTERWORTH_Q,
+            ),
+            tilt: params.tilt,
+            tilt_pre_coefficients: BiquadCoefficients::tilt(
+                sample_rate,
+                TILT_PIVOT_HZ,
+                params.tilt,
+            ),
+            tilt_post_coefficients: BiquadCoefficients::tilt(
+                sample_rate,
+                TILT_PIVOT_HZ,
+                -params.tilt,
+            ),
+            // This is computed with the first sample, since it depends on the clip stage's rate
+            crossover_settings: (0.0, 0.0, 0.0),
+            crossover_coefficients: CrossoverCoefficients::default(),
+            hysteresis_coefficient: 0.0,
+            // The same goes for this
+            transparent_settings: (0.0, 0.0, 0.0),
+            transparent_coefficients: EnvelopeCoefficients::new(sample_rate, 0.0, 0.0),
+            clip_release_settings: (0.0, 0.0),
+            clip_release_coefficient: 0.0,
+            tone_hz: params.tone_hz,
+            tone_coefficients: BiquadCoefficients::lowpass(
+                sample_rate,
+                params.tone_hz,
+                BUTTERWORTH_Q,
+            ),
+
+            bypass_amount: if params.bypass { 1.0 } else { 0.0 },
+            bypass_fade_step: (BYPASS_FADE_MS / 1000.0 * sample_rate).recip(),
+
+            idle: false,
+            silent_samples: 0,
+            idle_after_samples: (IDLE_AFTER_MS * sample_rate / 1000.0).round() as usize,
+            silent_input_samples: usize::MAX,
+            driven_peaks: [0.0; 2],
+            driven_peak_frames: 0,
+
+            over_hold_samples: (OVER_HOLD_MS * sample_rate / 1000.0).round() as usize,
+            over_hold_remaining: 0,
+
+            meters: Meters::silent(),
+            clip_window: RmsWindow::new(rms_window_samples * num_channels),
+            correlation_window: RmsWindow::new(rms_window_samples),
+            peak_hold: params.peak_hold,
+
+            learner: ThresholdLearner::default(),
+            learn_capture_samples: (LEARN_CAPTURE_MS * sample_rate / 1000.0).round() as usize,
+
+            transfer_table: Box::default(),
+            shared_transfer_table: Arc::default(),
+        };
+        engine.smoothers.reset(&params);
+
+        engine
+    }
+
+    /// Set new targets for the parameters. Levels and frequencies glide to their new values,
+    /// everything else takes effect with the next block.
+    pub fn set_params(&mut self, params: ClipEngineParams) {
+        self.params = params.resolve();
+    }
+
+    /// Take the table curve's table from `shared` from now on, starting with its current points.
+    /// This allocates, so it's not meant for the audio thread.
+    pub fn set_transfer_table(&mut self, shared: Arc<SharedTransferTable>) {
+        *self.transfer_table = TransferTable::new(&shared.points());
+        self.shared_transfer_table = shared;
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn params(&self) -> &ClipEngineParams {
+        &self.params
+    }
+
+    /// Pass on the host's tempo and musical position for the next block, which the threshold LFO
+    /// locks to.
+    pub fn set_transport(&mut self, tempo_bpm: Option<f64>, position_beats: Option<f64>) {
+        self.tempo_bpm = tempo_bpm;
+        self.position_beats = position_beats;
+    }
+
+    /// The total delay of the processed signal for the current parameters. Every stage that
+    /// delays the signal needs to be accounted for here.
+    pub fn latency_samples(&self) -> u32 {
+        self.stereo_oversamplers.latency(
+            self.params.os_filter,
+            self.params.os_quality,
+            self.params.oversampling.stages(),
+        ) + self.lookahead_samples() as u32
+    }
+
+    /// How many more samples need to be processed before the last non-silent input sample has
+    /// made it through the lookahead and the oversampling filters, or zero if nothing is buffered.
+    /// The filters' own decay is far below the silence threshold by then.
+    pub fn tail_samples(&self) -> u32 {
+        let latency_samples = self.latency_samples() as usize;
+        latency_samples.saturating_sub(self.silent_input_samples) as u32
+    }
+
+    fn lookahead_samples(&self) -> usize {
+        (self.params.lookahead_ms * self.sample_rate / 1000.0).round() as usize
+    }
+
+    /// How long a driven input sample can take to make it through the lookahead and the
+    /// upsampling filters.
+    fn driven_peak_span(&self) -> usize {
+        self.lookahead_samples() + UPSAMPLING_MEMORY_SAMPLES
+    }
+
+    /// Whether the hard clipper is certain to pass every sample of the next `num_samples` frames
+    /// through untouched, so the clip stage can skip the curve for the entire block. Most of the
+    /// time nothing gets near the threshold, and a single scan over the block is far cheaper than
+    /// running the curve on every oversampled sample.
+    ///
+    /// The clip stage's input is bounded from `block_peak`, the host input's peak for the block,
+    /// with the highest gain and width any smoother can reach within the block and the
+    /// upsampling filters' overshoot. The lookahead and the limiter only ever lower the level. The
+    /// ceilings are bounded from below the same way, with every modulation at its deepest. This
+    /// also records the block in `driven_peaks`, so it needs to be called exactly once per block.
+    fn clip_stage_passes_through(
+        &mut self,
+        params: &ClipEngineParams,
+        block_peak: f32,
+        num_samples: usize,
+        num_channels: usize,
+        sidechain_enabled: bool,
+    ) -> bool {
+        let smoothers = &self.smoothers;
+        let (_, gain_db) = smoothers.gain.range(params.gain);
+        let (_, drive_db) = smoothers.drive.range(params.drive);
+        // The width can only raise a stereo pair's peak above the input's when widening
+        let width = if num_channels == 2 {
+            smoothers.width.range(params.width).1.max(1.0)
+        } else {
+            1.0
+        };
+        let driven_peak = block_peak * db_to_gain(gain_db + drive_db) * width;
+        if self.driven_peak_frames >= self.driven_peak_span() {
+            self.driven_peaks = [self.driven_peaks[1], 0.0];
+            self.driven_peak_frames = 0;
+        }
+        self.driven_peaks[1] = self.driven_peaks[1].max(driven_peak);
+        self.driven_peak_frames += num_samples;
+
+        // Anything that shapes the signal below the threshold needs the full path
+        let smoothers = &self.smoothers;
+        let settled_at_zero = [
+            (&smoothers.tilt, params.tilt),
+            (&smoothers.saturation, params.saturation),
+            (&smoothers.character, params.character),
+            (&smoothers.two_stage, two_stage_amount(params)),
+        ]
+        .into_iter()
+        .all(|(smoother, target)| smoother.range(target) == (0.0, 0.0));
+        if params.curve != ClipCurve::Hard
+            || params.multiband_enable
+            || params.pre_hpf_enable
+            || !settled_at_zero
+        {
+            return false;
+        }
+
+        let (bias_low, bias_high) = smoothers.bias.range(params.bias);
+        let upsampling_gain = if params.oversampling.stages() > 0 {
+            MAX_UPSAMPLING_GAIN
+        } else {
+            1.0
+        };
+        let signal_peak = upsampling_gain
+            * (self.driven_peaks[0].max(self.driven_peaks[1])
+                + bias_low.abs().max(bias_high.abs()));
+
+        let threshold_db = [
+            (&smoothers.threshold, params.threshold),
+            (&smoothers.threshold_pos, params.threshold_pos),
+            (&smoothers.threshold_neg, params.threshold_neg),
+            (&smoothers.threshold_r, params.threshold_r),
+        ]
+        .into_iter()
+        .map(|(smoother, target)| smoother.range(target).0)
+        .fold(f32::INFINITY, f32::min);
+        let (_, link_gain_threshold) = smoothers
+            .link_gain_threshold
+            .range(link_gain_threshold_amount(params));
+        let (symmetry_low, symmetry_high) = smoothers.symmetry.range(params.symmetry);
+        // The followers only decay while the sidechain is disabled
+        let sidechain_level = if sidechain_enabled {
+            1.0
+        } else {
+            self.channels[..num_channels]
+                .iter()
+                .map(|channel| channel.sidechain_follower.envelope())
+                .fold(0.0, f32::max)
+                .min(1.0)
+        };
+        let (_, sidechain_depth_db) = smoothers.sidechain_depth.range(params.sidechain_depth);
+        let (_, mod_depth_db) = smoothers.mod_depth.range(mod_depth(params));
+        let (_, pd_amount_db) = smoothers.pd_amount.range(pd_amount(params));
+        let ceiling_db = threshold_db
+            - link_gain_threshold * gain_db.max(0.0)
+            - symmetry_low.abs().max(symmetry_high.abs()) * MAX_SYMMETRY_DB
+            - sidechain_depth_db.max(0.0) * sidechain_level
+            - mod_depth_db.max(0.0)
+            - pd_amount_db.max(0.0)
+            - PASS_THROUGH_MARGIN_DB;
+        let (_, knee_db) = smoothers.knee.range(params.knee);
+
+        signal_peak < knee_start(db_to_gain(ceiling_db), knee_from_db(knee_db))
+    }
+
+    pub fn meters(&self) -> Meters {
+        self.meters
+    }
+
+    /// The threshold in decibels suggested by the last `learn` capture, if it finished since this
+    /// was last called. Applying it is up to the caller.
+    pub fn take_learned_threshold(&mut self) -> Option<f32> {
+        self.learner.take_suggestion()
+    }
+
+    /// Zero the peak and true peak meters, which also clears the peaks held by `peak_hold`.
+    pub fn clear_peaks(&mut self) {
+        self.meters.input_peak = 0.0;
+        self.meters.output_peak = 0.0;
+        self.meters.true_peak = 0.0;
+    }
+
+    /// Clear all filter and delay state, snap the smoothers to the current parameters, and zero
+    /// the meters.
+    pub fn reset(&mut self) {
+        for channel in &mut self.channels {
+            channel.reset();
+        }
+        self.stereo_oversamplers.reset();
+        self.smoothers.reset(&self.params);
+        self.lfo.reset();
+        self.idle = false;
+        self.silent_samples = 0;
+        self.silent_input_samples = usize::MAX;
+        self.driven_peaks = [0.0; 2];
+        self.driven_peak_frames = 0;
+        self.learner.reset();
+        self.bypass_amount = if self.params.bypass { 1.0 } else { 0.0 };
+        self.reset_meters();
+    }
+
+    /// Set all meters back to their readings for silence, see `Meters::silent()`, and zero the
+    /// windows behind them. That includes the peaks held by `peak_hold`, the clip ratio, the over
+    /// indicator, and the count of non-finite samples. The signal chain itself keeps running
+    /// undisturbed.
+    pub fn reset_meters(&mut self) {
+        for channel in &mut self.channels {
+            channel.input_rms.reset();
+            channel.output_rms.reset();
+            channel.clipper.take_over_ratio();
+        }
+        self.over_hold_remaining = 0;
+        self.meters = Meters::silent();
+        self.clip_window.reset();
+        self.correlation_window.reset();
+    }
+
+    /// Process a block in place, one slice per channel. See
+    /// `ClipEngine::process_block_with_sidechain()`.
+    pub fn process_block(&mut self, channels: &mut [&mut [f32]]) {
+        self.process_block_with_sidechain::<&[f32]>(channels, &[]);
+    }
+
+    /// Process a block in place, one slice per channel, with every main channel following the
+    /// sidechain channel with the same index when the sidechain is enabled. Channels beyond the
+    /// count the engine was created with are left untouched, and all slices should be equally
+    /// long.
+    pub fn process_block_with_sidechain<S: AsRef<[f32]>>(
+        &mut self,
+        channels: &mut [&mut [f32]],
+        sidechain: &[S],
+    ) {
+        let num_channels = channels.len().min(self.channels.len());
+        let channels = &mut channels[..num_channels];
+        let Some(num_samples) = channels.iter().map(|channel| channel.len()).min() else {
+            return;
+        };
+        let _ftz = ScopedFtz::enable();
+
+        self.shared_transfer_table.update(&mut self.transfer_table);
+        let params = self.params;
+        let oversampling = params.oversampling;
+        let os_quality = params.os_quality;
+        let os_filter = params.os_filter;
+        if oversampling != self.oversampling
+            || os_quality != self.os_quality
+            || os_filter != self.os_filter
+        {
+            self.oversampling = oversampling;
+            self.os_quality = os_quality;
+            self.os_filter = os_filter;
+            for channel in &mut self.channels {
+                channel.oversamplers.reset_filter(os_filter, os_quality);
+                channel.clipper.crossover.reset();
+            }
+            self.stereo_oversamplers.reset_filter(os_filter, os_quality);
+        }
+        let oversampling_stages = oversampling.stages();
+        let oversampling_latency =
+            self.stereo_oversamplers
+                .latency(os_filter, os_quality, oversampling_stages) as usize;
+
+        let lookahead_samples = self.lookahead_samples();
+        for channel in &mut self.channels {
+            channel.lookahead.set_length(lookahead_samples);
+        }
+
+        let smoothing =
+            SmoothingConfig::new(params.smoothing_mode, params.smoothing_ms, self.sample_rate);
+        let frequency_smoothing = SmoothingConfig::new(
+            SmoothingMode::Linear,
+            FREQUENCY_SMOOTHING_MS,
+            self.sample_rate,
+        );
+        let pre_hpf_enable = params.pre_hpf_enable;
+        let pre_hpf_mode = params.pre_hpf_mode;
+        let ceiling_enable = params.ceiling_enable;
+        let f64_filters = params.f64_filters;
+        let dither_lsb = if params.dither_enable {
+            Some(Dither::lsb(params.dither_bits))
+        } else {
+            None
+        };
+        // The detection filter mode clips at the base rate, everything else at the oversampled rate
+        let detection_filter = pre_hpf_enable && pre_hpf_mode == PreFilterMode::Detection;
+        let bypass_target = if params.bypass { 1.0 } else { 0.0 };
+        // Listening works whether or not the sidechain drives the threshold, so it can be set up
+        // before enabling it
+        let listen_sidechain = if params.sc_listen {
+            Some(sidechain)
+        } else {
+            None
+        };
+        let sidechain = if params.sidechain_enable {
+            Some(sidechain)
+        } else {
+            None
+        };
+        // Non-finite samples are silenced before they go anywhere, and infinities simply rule out
+        // the fast path
+        let block_peak = channels
+            .iter()
+            .flat_map(|samples| &samples[..num_samples])
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        let pass_through = self.clip_stage_passes_through(
+            &params,
+            block_peak,
+            num_samples,
+            num_channels,
+            sidechain.is_some(),
+        );
+        let sidechain_envelope =
+            EnvelopeCoefficients::new(self.sample_rate, params.sc_attack_ms, params.sc_release_ms);
+        let pd_peak_envelope = EnvelopeCoefficients::new(self.sample_rate, 0.0, PD_PEAK_RELEASE_MS);
+        let pd_average_envelope =
+            EnvelopeCoefficients::new(self.sample_rate, params.pd_time_ms, params.pd_time_ms);
+        let limiter_envelope = EnvelopeCoefficients::new(
+            self.sample_rate,
+            LIMITER_ATTACK_MS,
+            params.limiter_release_ms,
+        );
+        if let Some(position_beats) = self.position_beats.take() {
+            self.lfo.sync(position_beats);
+        }
+        let lfo_cycle_beats = params.mod_rate.cycle_beats();
+        let lfo_beats_per_sample =
+            self.tempo_bpm.unwrap_or(FALLBACK_TEMPO_BPM) / 60.0 / self.sample_rate as f64;
+
+        // The meters fall by 12 dB over `meter_decay_ms`
+        let meter_decay_weight = 0.25f64
+            .powf((self.sample_rate as f64 * params.meter_decay_ms as f64 / 1000.0).recip())
+            as f32;
+        let peak_decay_weight = if params.peak_hold {
+            1.0
+        } else {
+            meter_decay_weight
+        };
+        if params.peak_hold && !self.peak_hold {
+            self.clear_peaks();
+        }
+        self.peak_hold = params.peak_hold;
+        self.learner
+            .set_armed(params.learn, self.learn_capture_samples);
+
+        let mut input_peak = self.meters.input_peak;
+        let mut output_peak = self.meters.output_peak;
+        let mut true_peak = self.meters.true_peak;
+        let mut gain_reduction_db = self.meters.gain_reduction_db;
+        let mut num_non_finite_samples = 0u32;
+        let mut over = false;
+        let over_tolerance = db_to_gain(OVER_TOLERANCE_DB);
+
+        for sample_idx in 0..num_samples {
+            let smoothed = self
+                .smoothers
+                .next(&params, &smoothing, &frequency_smoothing);
+
+            let gain_db = smoothed.gain;
+            let drive_db = smoothed.drive;
+            let gain = db_to_gain(gain_db + drive_db);
+
+            // With the gain linked to the threshold, turning up the gain lowers every threshold by
+            // the same amount
+            let threshold_offset_db = -smoothed.link_gain_threshold * gain_db;
+            let threshold_db = smoothed.threshold + threshold_offset_db;
+            let threshold_pos_db = smoothed.threshold_pos + threshold_offset_db;
+            let threshold_neg_db = smoothed.threshold_neg + threshold_offset_db;
+            let threshold_r_db = smoothed.threshold_r + threshold_offset_db;
+            let knee = knee_from_db(smoothed.knee);
+
+            let sidechain_depth_db = smoothed.sidechain_depth;
+            // This always runs so the LFO stays in time while it's disabled
+            let lfo = self.lfo.next(lfo_cycle_beats, lfo_beats_per_sample);
+            let lfo_gain = db_to_gain(-smoothed.mod_depth * lfo);
+
+            let symmetry = smoothed.symmetry;
+            let ceilings = |threshold_db: f32| {
+                if params.link_thresholds {
+                    ceilings_with_symmetry(threshold_db, threshold_db, symmetry)
+                } else {
+                    ceilings_with_symmetry(threshold_pos_db, threshold_neg_db, symmetry)
+                }
+            };
+            let primary_ceilings = ceilings(threshold_db);
+            let second_ceilings = if params.link_channels {
+                primary_ceilings
+            } else {
+                ceilings(threshold_r_db)
+            };
+
+            let pre_hpf_hz = smoothed.pre_hpf_hz;
+            if pre_hpf_hz != self.pre_hpf_hz {
+                self.pre_hpf_hz = pre_hpf_hz;
+                self.pre_hpf_coefficients =
+                    BiquadCoefficients::highpass(self.sample_rate, pre_hpf_hz, BUTTERWORTH_Q);
+            }
+            let tilt = smoothed.tilt;
+            if tilt != self.tilt {
+                self.tilt = tilt;
+                self.tilt_pre_coefficients =
+                    BiquadCoefficients::tilt(self.sample_rate, TILT_PIVOT_HZ, tilt);
+                self.tilt_post_coefficients =
+                    BiquadCoefficients::tilt(self.sample_rate, TILT_PIVOT_HZ, -tilt);
+            }
+            let band_ceilings = [
+                smoothed.threshold_low,
+                smoothed.threshold_mid,
+                smoothed.threshold_high,
+            ]
+            .map(|threshold_db| db_to_gain(threshold_db + threshold_offset_db));
+
+            let tone_hz = smoothed.tone_hz;
+            if tone_hz != self.tone_hz {
+                self.tone_hz = tone_hz;
+                self.tone_coefficients =
+                    BiquadCoefficients::lowpass(self.sample_rate, tone_hz, BUTTERWORTH_Q);
+            }
+
+            let bias = smoothed.bias;
+            let clip_settings = ClipSettings {
+                curve: params.curve,
+                side: params.clip_side,
+                knee,
+                folds: 1.0 + smoothed.fold_amount * (MAX_FOLD_DRIVE - 1.0),
+                order: smoothed.shape_order,
+                saturation: saturation_with_character(smoothed.saturation, smoothed.character),
+                sat_drive: db_to_gain(smoothed.sat_drive),
+                two_stage: smoothed.two_stage,
+                table: &self.transfer_table,
+            };
+
+            let makeup_gain_db = smoothed.auto_gain
+                * makeup_gain_db(
+                    gain_db + drive_db,
+                    if params.link_thresholds {
+                        threshold_db
+                    } else {
+                        (threshold_pos_db + threshold_neg_db) / 2.0
+                    },
+                );
+            // The tanh curve is quieter than the hard clipper, so whatever saturation `character`
+            // adds on top of `saturation` comes with its share of the loudness it costs
+            let character_makeup_db =
+                if matches!(params.curve, ClipCurve::Hard | ClipCurve::HardAdaa) {
+                    (clip_settings.saturation - smoothed.saturation) * CHARACTER_MAKEUP_DB
+                } else {
+                    0.0
+                };
+            let output_gain_db = smoothed.output_gain + makeup_gain_db + character_makeup_db;
+            let output_gain = db_to_gain(output_gain_db);
+
+            let mix = smoothed.mix;
+            let delta_gain = db_to_gain(smoothed.delta_gain);
+            let output_ceiling = db_to_gain(smoothed.ceiling_db);
+            // The oversampled clamp happens before the output gain is applied
+            let clip_stage_ceiling = output_ceiling / output_gain;
+
+            self.bypass_amount = if bypass_target > self.bypass_amount {
+                (self.bypass_amount + self.bypass_fade_step).min(bypass_target)
+            } else {
+                (self.bypass_amount - self.bypass_fade_step).max(bypass_target)
+            };
+
+            input_peak *= peak_decay_weight;
+            output_peak *= peak_decay_weight;
+            true_peak *= peak_decay_weight;
+            gain_reduction_db *= meter_decay_weight;
+
+            // A single NaN or infinity coming from the host would otherwise end up in every
+            // filter's state and never leave again
+            let mut frame_peak = 0.0f32;
+            for (samples, channel) in channels.iter_mut().zip(&mut self.channels) {
+                let sample = &mut samples[sample_idx];
+                if !sample.is_finite() {
+                    *sample = 0.0;
+                    num_non_finite_samples += 1;
+                }
+                frame_peak = frame_peak.max(sample.abs());
+                channel.input_rms.push(*sample);
+            }
+            input_peak = input_peak.max(frame_peak);
+            self.silent_input_samples = if frame_peak > IDLE_THRESHOLD {
+                0
+            } else {
+                self.silent_input_samples.saturating_add(1)
+            };
+
+            // The two channels are processed completely independently, so the entire chain
+            // including the dry path runs in the mid/side domain. The width is applied to the dry
+            // path as well, so mix and delta only reflect what the clipper does.
+            let is_stereo = num_channels == 2;
+            let mid_side = params.stereo_mode == StereoMode::MidSide && is_stereo;
+            let width = smoothed.width;
+            if mid_side || (is_stereo && width != 1.0) {
+                map_stereo_pair(channels, sample_idx, |left, right| {
+                    let (mid, side) = mid_side_encode(left, right);
+                    if mid_side {
+                        (mid, side * width)
+                    } else {
+                        mid_side_decode(mid, side * width)
+                    }
+                });
+            }
+
+            for (channel_idx, (samples, channel)) in
+                channels.iter().zip(&mut self.channels).enumerate()
+            {
+                let dry = samples[sample_idx];
+
+                // The follower keeps running while the sidechain is disabled so it decays back to
+                // zero instead of picking up where it left off
+                let sidechain_input = sidechain.map_or(0.0, |sidechain| {
+                    sidechain_sample(sidechain, channel_idx, sample_idx)
+                });
+                let sidechain_level = channel
+                    .sidechain_follower
+                    .process(&sidechain_envelope, sidechain_input);
+                // Like the sidechain's, these followers keep running while disabled
+                let pd_peak = channel.pd_peak.process(&pd_peak_envelope, dry);
+                let pd_average = channel.pd_average.process(&pd_average_envelope, pd_peak);
+                let pd_offset_db =
+                    program_dependent_offset_db(pd_peak, pd_average, smoothed.pd_amount);
+                let ceiling_gain =
+                    db_to_gain(pd_offset_db - sidechain_depth_db * sidechain_level.min(1.0))
+                        * lfo_gain;
+
+                let (ceiling_pos, ceiling_neg) = if channel_idx == 1 {
+                    second_ceilings
+                } else {
+                    primary_ceilings
+                };
+                let ceilings = Ceilings {
+                    pos: ceiling_pos * ceiling_gain,
+                    neg: ceiling_neg * ceiling_gain,
+                    bands: band_ceilings.map(|ceiling| ceiling * ceiling_gain),
+                };
+
+                // Everything after the lookahead and the oversampling filters runs behind the
+                // input, so the dry signal used for mixing, delta, and bypass needs to be delayed
+                // by the same amount. Otherwise the two would comb filter when blended, and the
+                // delta output wouldn't cancel out for signals that aren't being clipped.
+                let delayed_dry = channel
+                    .dry_delay
+                    .process(dry, lookahead_samples + oversampling_latency);
+
+                let driven_input = dry * gain;
+                let driven = channel
+                    .driven_delay
+                    .process(driven_input, lookahead_samples + oversampling_latency);
+                if self.learner.is_capturing() {
+                    self.learner.push(driven_input);
+                }
+
+                let ducked = channel
+                    .lookahead
+                    .process(driven_input, ceilings.pos, ceilings.neg);
+                // The limiter takes most of the level off smoothly before anything gets clipped.
+                // Like the filters below it always runs so enabling it doesn't start from stale
+                // state.
+                let ducked = channel.limiter.process(
+                    &limiter_envelope,
+                    ducked,
+                    ceilings.pos,
+                    ceilings.neg,
+                    smoothed.limiter,
+                );
+                // The tilt's pre-emphasis weights the clipping by frequency, and is undone again
+                // by the de-emphasis once the signal leaves the clip stage. At zero tilt both
+                // pass the signal through exactly.
+                let ducked =
+                    channel
+                        .tilt_pre
+                        .process_with(&self.tilt_pre_coefficients, ducked, f64_filters);
+                // Like the DC blocker, the filter always runs so enabling it doesn't start from
+                // stale state
+                let filtered =
+                    channel
+                        .pre_hpf
+                        .process_with(&self.pre_hpf_coefficients, ducked, f64_filters);
+                let (clipper_input, detector) = match (pre_hpf_enable, pre_hpf_mode) {
+                    (false, _) => (ducked + bias, None),
+                    (true, PreFilterMode::Audio) => (filtered + bias, None),
+                    (true, PreFilterMode::Detection) => (ducked + bias, Some(filtered + bias)),
+                };
+
+                channel.frame = ChannelFrame {
+                    delayed_dry,
+                    driven,
+                    exceeds_threshold: ceilings.exceeded_by(detector.unwrap_or(clipper_input)),
+                    clipper_input,
+                    detector,
+                    detector_gain: 1.0,
+                    ceilings,
+                    clipped: 0.0,
+                };
+            }
+            self.learner.end_frame(threshold_offset_db);
+
+            // Below `IDLE_THRESHOLD` the clip stage leaves the signal alone and the oversampling
+            // filters only delay it, so they can be swapped for a plain delay. This wakes up
+            // before the frame that crosses the threshold gets clipped, and until then the
+            // filters' history only held silence.
+            let clipper_peak = self.channels[..num_channels]
+                .iter()
+                .map(|channel| channel.frame.clipper_input.abs())
+                .fold(0.0, f32::max);
+            if clipper_peak > IDLE_THRESHOLD || !clipper_peak.is_finite() {
+                self.silent_samples = 0;
+                if self.idle {
+                    self.idle = false;
+                    for channel in &mut self.channels {
+                        channel.oversamplers.reset_filter(os_filter, os_quality);
+                    }
+                    self.stereo_oversamplers.reset_filter(os_filter, os_quality);
+                }
+            } else if !self.idle && !detection_filter && oversampling_stages > 0 {
+                self.silent_samples += 1;
+                if self.silent_samples >= self.idle_after_samples {
+                    self.idle = true;
+                    // This may still hold audio from the last time the detection filter was used
+                    for channel in &mut self.channels {
+                        channel.detection_delay.reset();
+                    }
+                }
+            }
+            let oversample = !detection_filter && !self.idle;
+
+            // The crossover runs at the clip stage's rate
+            let clip_sample_rate = if oversample {
+                self.sample_rate * (1 << oversampling_stages) as f32
+            } else {
+                self.sample_rate
+            };
+            let crossover_settings = (
+                clip_sample_rate,
+                smoothed.crossover_lo_hz,
+                smoothed.crossover_hi_hz,
+            );
+            if crossover_settings != self.crossover_settings {
+                self.crossover_settings = crossover_settings;
+                let (sample_rate, low_hz, high_hz) = crossover_settings;
+                self.crossover_coefficients =
+                    CrossoverCoefficients::new(sample_rate, low_hz, high_hz);
+                self.hysteresis_coefficient = Hysteresis::coefficient(sample_rate);
+            }
+            let transparent_settings = (
+                clip_sample_rate,
+                params.transparent_attack_ms,
+                params.transparent_release_ms,
+            );
+            if transparent_settings != self.transparent_settings {
+                self.transparent_settings = transparent_settings;
+                let (sample_rate, attack_ms, release_ms) = transparent_settings;
+                self.transparent_coefficients =
+                    EnvelopeCoefficients::new(sample_rate, attack_ms, release_ms);
+            }
+            let clip_release_settings = (clip_sample_rate, params.clip_release_ms);
+            if clip_release_settings != self.clip_release_settings {
+                self.clip_release_settings = clip_release_settings;
+                let (sample_rate, release_ms) = clip_release_settings;
+                self.clip_release_coefficient = ClipRelease::coefficient(sample_rate, release_ms);
+            }
+
+            let clip_stage = ClipStage {
+                settings: clip_settings,
+                multiband: params.multiband_enable,
+                crossover_coefficients: &self.crossover_coefficients,
+                oversampled_ceiling: (ceiling_enable && oversampling_stages > 0)
+                    .then_some(clip_stage_ceiling),
+                bias,
+                analog_mode: params.analog_mode,
+                hysteresis_coefficient: self.hysteresis_coefficient,
+                release_coefficient: self.clip_release_coefficient,
+                transparent_coefficients: self.transparent_coefficients,
+                link_amount: if params.stereo_link {
+                    smoothed.link_amount
+                } else {
+                    0.0
+                },
+                pass_through,
+            };
+            // Stereo pairs share the oversampling filters so both channels can be filtered with
+            // the same SIMD instructions. That also keeps both channels' filter state in lockstep,
+            // so with either filter the oversampling can't introduce a phase difference between
+            // them. The detection filter mode doesn't oversample.
+            match &mut self.channels[..num_channels] {
+                [left, right] if oversample => {
+                    let [left_clipped, right_clipped] = self.stereo_oversamplers.process(
+                        os_filter,
+                        os_quality,
+                        oversampling_stages,
+                        [left.frame.clipper_input, right.frame.clipper_input],
+                        |signals @ [left_signal, right_signal]| {
+                            let clipped = [
+                                left.clipper
+                                    .clip(&clip_stage, &left.frame.ceilings, left_signal),
+                                right.clipper.clip(
+                                    &clip_stage,
+                                    &right.frame.ceilings,
+                                    right_signal,
+                                ),
+                            ];
+                            let clipped = if clip_stage.link_amount > 0.0 {
+                                link_gain_reduction(signals, clipped, bias, clip_stage.link_amount)
+                            } else {
+                                clipped
+                            };
+
+                            clipped.map(|clipped| clip_stage.clamp_oversampled(clipped))
+                        },
+                    );
+                    left.frame.clipped = left_clipped - bias;
+                    right.frame.clipped = right_clipped - bias;
+                }
+                channel_states => {
+                    // Scaling the full-band signal by the detector's gain change only makes sense
+                    // at the base rate. The gains are worked out first so stereo pairs can share
+                    // them.
+                    for channel in channel_states.iter_mut() {
+                        if let Some(detector) = channel.frame.detector {
+                            let ceilings = channel.frame.ceilings;
+                            let detector_clipped =
+                                channel.clipper.clip(&clip_stage, &ceilings, detector);
+                            channel.frame.detector_gain = clip_gain(detector, detector_clipped);
+                        }
+                    }
+                    if let [left, right] = channel_states
+                        && clip_stage.link_amount > 0.0
+                    {
+                        [left.frame.detector_gain, right.frame.detector_gain] = link_gains(
+                            [left.frame.detector_gain, right.frame.detector_gain],
+                            clip_stage.link_amount,
+                        );
+                    }
+
+                    for channel in channel_states {
+                        let ceilings = channel.frame.ceilings;
+                        let clipped = match channel.frame.detector {
+                            // This still needs to line up with the oversampled path
+                            Some(_) => channel.detection_delay.process(
+                                channel.frame.clipper_input * channel.frame.detector_gain,
+                                oversampling_latency,
+                            ),
+                            // This still needs to line up with the oversampled path so waking up
+                            // doesn't shift the signal
+                            None if !oversample => channel.detection_delay.process(
+                                channel.clipper.clip(
+                                    &clip_stage,
+                                    &ceilings,
+                                    channel.frame.clipper_input,
+                                ),
+                                oversampling_latency,
+                            ),
+                            None => channel.oversamplers.process(
+                                os_filter,
+                                os_quality,
+                                oversampling_stages,
+                                channel.frame.clipper_input,
+                                |signal| {
+                                    clip_stage.clamp_oversampled(channel.clipper.clip(
+                                        &clip_stage,
+                                        &ceilings,
+                                        signal,
+                                    ))
+                                },
+                            ),
+                        };
+                        channel.frame.clipped = clipped - bias;
+                    }
+                }
+            }
+
+            // When every sample goes through the curve on its own, the meter can use the curve's
+            // static reduction. The other modes are only measured by comparing the output with
+            // the input.
+            let static_reduction = !params.multiband_enable
+                && !params.analog_mode
+                && !params.stereo_link
+                && params.clip_release_ms <= 0.0
+                && params.curve != ClipCurve::Transparent;
+            for (samples, channel) in channels.iter_mut().zip(&mut self.channels) {
+                let ChannelFrame {
+                    delayed_dry,
+                    driven,
+                    exceeds_threshold,
+                    clipper_input,
+                    detector,
+                    ceilings,
+                    clipped,
+                    ..
+                } = channel.frame;
+
+                // This needs to be taken every frame so the counts don't carry over. The detection
+                // filter mode never runs the clip stage on the audio, so it always has to fall
+                // back to the base rate.
+                let over_ratio = channel.clipper.take_over_ratio();
+                self.clip_window.push_square(
+                    match over_ratio.filter(|_| params.clip_ratio_oversampled) {
+                        Some(ratio) => ratio,
+                        None if exceeds_threshold => 1.0,
+                        None => 0.0,
+                    },
+                );
+
+                let clipped = channel.tilt_post.process_with(
+                    &self.tilt_post_coefficients,
+                    clipped,
+                    f64_filters,
+                );
+                // The blocker runs even while disabled so toggling it doesn't start from stale state
+                let blocked = channel.dc_blocker.process(clipped, f64_filters);
+                let shaped = if params.dc_block { blocked } else { clipped };
+                let toned =
+                    channel
+                        .tone_lpf
+                        .process_with(&self.tone_coefficients, shaped, f64_filters);
+                let wet = if params.tone_enable { toned } else { shaped } * output_gain;
+                let difference = mix * (wet - delayed_dry);
+                let processed = if params.delta {
+                    let delta = match params.delta_mode {
+                        DeltaMode::VsInput => difference,
+                        DeltaMode::VsGained => mix * (wet - driven * output_gain),
+                    };
+                    delta * delta_gain
+                } else if ceiling_enable {
+                    hard_clip(delayed_dry + difference, output_ceiling)
+                } else {
+                    delayed_dry + difference
+                } * smoothed.polarity;
+                samples[sample_idx] = processed + self.bypass_amount * (delayed_dry - processed);
+
+                // Only take the logarithms when something is actually being removed
+                if static_reduction {
+                    let ducking_gain = channel.lookahead.gain() * channel.limiter.gain();
+                    let ducking_db = if ducking_gain < 1.0 {
+                        -gain_to_db(ducking_gain)
+                    } else {
+                        0.0
+                    };
+                    let clip_db = static_gain_reduction_db(
+                        &clip_settings,
+                        detector.unwrap_or(clipper_input),
+                        ceilings.pos,
+                        ceilings.neg,
+                    );
+                    gain_reduction_db = gain_reduction_db.max(ducking_db + clip_db);
+                } else if driven.abs() > clipped.abs() {
+                    gain_reduction_db =
+                        gain_reduction_db.max(gain_to_db(driven.abs()) - gain_to_db(clipped.abs()));
+                }
+            }
+
+            if mid_side {
+                map_stereo_pair(channels, sample_idx, mid_side_decode);
+            }
+
+            let over_level = if ceiling_enable { output_ceiling } else { 1.0 } * over_tolerance;
+            for (channel_idx, (samples, channel)) in
+                channels.iter_mut().zip(&mut self.channels).enumerate()
+            {
+                let sample = &mut samples[sample_idx];
+
+                // Bypassing crossfades from the sidechain back to the dry signal
+                if let Some(sidechain) = listen_sidechain {
+                    let listened = sidechain_sample(sidechain, channel_idx, sample_idx);
+                    *sample += (1.0 - self.bypass_amount) * (listened - *sample);
+                }
+
+                // This fades out with the rest of the processing when bypassing
+                if let Some(lsb) = dither_lsb {
+                    *sample += (1.0 - self.bypass_amount) * channel.dither.next(lsb);
+                }
+
+                over |= sample.abs() >= over_level;
+                output_peak = output_peak.max(sample.abs());
+                channel.output_rms.push(*sample);
+                channel
+                    .true_peak_upsampler
+                    .upsample(TRUE_PEAK_STAGES, *sample, |interpolated| {
+                        true_peak = true_peak.max(interpolated.abs());
+                    });
+            }
+            if let [left, right] = &channels[..num_channels] {
+                self.correlation_window
+                    .push_square(left[sample_idx] * right[sample_idx]);
+            }
+        }
+
+        self.meters.input_peak = input_peak;
+        self.meters.output_peak = output_peak;
+        self.meters.true_peak = true_peak;
+        self.meters.gain_reduction_db = gain_reduction_db;
+        self.over_hold_remaining = if over {
+            self.over_hold_samples
+        } else {
+            self.over_hold_remaining.saturating_sub(num_samples)
+        };
+        self.meters.over = over || self.over_hold_remaining > 0;
+        let input_power: f32 = self.channels[..num_channels]
+            .iter()
+            .map(|c| c.input_rms.mean_square())
+            .sum();
+        let output_power: f32 = self.channels[..num_channels]
+            .iter()
+            .map(|c| c.output_rms.mean_square())
+            .sum();
+        self.meters.input_rms = (input_power / num_channels as f32).sqrt();
+        self.meters.output_rms = (output_power / num_channels as f32).sqrt();
+        self.meters.non_finite_samples = self
+            .meters
+            .non_finite_samples
+            .saturating_add(num_non_finite_samples);
+        self.meters.clip_ratio = self.clip_window.mean_square();
+        self.meters.correlation = match &self.channels[..num_channels] {
+            [left, right] => correlation(
+                self.correlation_window.mean(),
+                left.output_rms.mean_square(),
+                right.output_rms.mean_square(),
+            ),
+            _ => 1.0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pass_through_is_bit_identical_to_the_curve() {
+        // Well below a kneed threshold, through the lookahead and the oversampling filters
+        let params = ClipEngineParams {
+            gain: 2.0,
+            threshold: -6.0,
+            knee: 3.0,
+            oversampling: Oversampling::X4,
+            lookahead_ms: 1.0,
+            ..ClipEngineParams::default()
+        };
+        let mut fast = ClipEngine::new(48_000.0, 2, params);
+        let mut full = ClipEngine::new(48_000.0, 2, params);
+        let amplitude = db_to_gain(-30.0);
+        let input: Vec<[f32; 2]> = (0..8192)
+            .map(|sample_idx| {
+                let phase = sample_idx as f32 / 48_000.0 * std::f32::consts::TAU;
+                [(997.0 * phase).sin(), (1503.0 * phase).cos()].map(|sample| amplitude * sample)
+            })
+            .collect();
+
+        for block in input.chunks(256) {
+            // Recording an empty block leaves the span the history covers as it is
+            assert!(fast.clip_stage_passes_through(&params, amplitude, 0, 2, false));
+            // An unbounded history rules out the fast path for the next block
+            full.driven_peaks = [f32::INFINITY; 2];
+
+            let [fast_output, full_output] = [&mut fast, &mut full].map(|engine| {
+                let [mut left, mut right] = [0, 1]
+                    .map(|channel| block.iter().map(|frame| frame[channel]).collect::<Vec<_>>());
+                engine.process_block(&mut [&mut left, &mut right]);
+                [left, right]
+            });
+            for (fast_channel, full_channel) in fast_output.iter().zip(&full_output) {
+                for (fast_sample, full_sample) in fast_channel.iter().zip(full_channel) {
+                    assert_eq!(fast_sample.to_bits(), full_sample.to_bits());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Envelope following for the sidechain input.
+
+/// The per-sample smoothing factors for an `EnvelopeFollower`, derived from the attack and release
+/// times once per block.
+#[derive(Clone, Copy, Debug)]
+pub struct EnvelopeCoefficients {
+    attack: f32,
+    release: f32,
+}
+
+impl EnvelopeCoefficients {
+    /// The envelope covers about 63% of a step in its input after the attack or release time. A
+    /// time of zero follows the input instantly.
+    pub fn new(sample_rate: f32, attack_ms: f32, release_ms: f32) -> Self {
+        let coefficient = |time_ms: f32| {
+            let samples = time_ms / 1000.0 * sample_rate;
+            if samples > 0.0 {
+                (-samples.recip()).exp()
+            } else {
+                0.0
+            }
+        };
+
+        Self {
+            attack: coefficient(attack_ms),
+            release: coefficient(release_ms),
+        }
+    }
+}
+
+/// A peak envelope follower with separate attack and release times.
+#[derive(Clone, Copy, Default)]
+pub struct EnvelopeFollower {
+    envelope: f32,
+}
+
+impl EnvelopeFollower {
+    /// Follow the magnitude of `sample` and return the current envelope.
+    #[inline]
+    pub fn process(&mut self, coefficients: &EnvelopeCoefficients, sample: f32) -> f32 {
+        let input = sample.abs();
+        let coefficient = if input > self.envelope {
+            coefficients.attack
+        } else {
+            coefficients.release
+        };
+        self.envelope = input + coefficient * (self.envelope - input);
+
+        self.envelope
+    }
+
+    /// The envelope `process()` returned last.
+    pub fn envelope(&self) -> f32 {
+        self.envelope
+    }
+
+    pub fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+}
@@ -0,0 +1,157 @@
+//! Biquad filters for shaping the signal around the clip stage.
+
+use std::f32::consts::PI;
+
+/// The Q for a second order Butterworth response, being maximally flat without any resonance.
+pub const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Normalized biquad coefficients, following the formulas from Robert Bristow-Johnson's Audio EQ
+/// Cookbook. These are shared between all channels, while every channel keeps its own `Biquad`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiquadCoefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Default for BiquadCoefficients {
+    /// Passes the signal through unchanged.
+    fn default() -> Self {
+        Self {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+        }
+    }
+}
+
+impl BiquadCoefficients {
+    pub fn highpass(sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let (cos_omega, alpha) = Self::omega_alpha(sample_rate, frequency, q);
+        let a0 = 1.0 + alpha;
+        let b0 = (1.0 + cos_omega) / 2.0;
+
+        Self {
+            b0: b0 / a0,
+            b1: -(1.0 + cos_omega) / a0,
+            b2: b0 / a0,
+            a1: -2.0 * cos_omega / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+
+    pub fn lowpass(sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let (cos_omega, alpha) = Self::omega_alpha(sample_rate, frequency, q);
+        let a0 = 1.0 + alpha;
+        let b0 = (1.0 - cos_omega) / 2.0;
+
+        Self {
+            b0: b0 / a0,
+            b1: (1.0 - cos_omega) / a0,
+            b2: b0 / a0,
+            a1: -2.0 * cos_omega / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+
+    /// Passes all frequencies at unity gain while shifting the phase by up to 360 degrees around
+    /// `frequency`.
+    pub fn allpass(sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let (cos_omega, alpha) = Self::omega_alpha(sample_rate, frequency, q);
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b0: (1.0 - alpha) / a0,
+            b1: -2.0 * cos_omega / a0,
+            b2: (1.0 + alpha) / a0,
+            a1: -2.0 * cos_omega / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+
+    /// A first order tilt that raises the highs and lowers the lows by the same amount, with
+    /// unity gain at `pivot` and a slope of `slope_db_per_octave` there. The slope flattens out
+    /// away from the pivot, and a first order filter can't actually reach 6 dB per octave, so the
+    /// slope is limited to just under that. Negating the slope gives this filter's exact
+    /// inverse.
+    pub fn tilt(sample_rate: f32, pivot: f32, slope_db_per_octave: f32) -> Self {
+        // For `k * (s + 1/k) / (s + k)` with the pivot at one, the slope at the pivot is
+        // `20 * log10(2) * (k² - 1) / (k² + 1)` decibels per octave
+        let slope = (slope_db_per_octave / (20.0 * 2.0f32.log10())).clamp(-0.99, 0.99);
+        let k = ((1.0 + slope) / (1.0 - slope)).sqrt();
+        // The bilinear transform, prewarped so the pivot keeps its unity gain
+        let pivot = pivot.clamp(1.0, sample_rate * 0.49);
+        let c = (PI * pivot / sample_rate).tan().recip();
+        let a0 = c + k;
+
+        Self {
+            b0: k * (c + k.recip()) / a0,
+            b1: k * (k.recip() - c) / a0,
+            b2: 0.0,
+            a1: (k - c) / a0,
+            a2: 0.0,
+        }
+    }
+
+    /// The cosine of the angular frequency and the bandwidth term shared by all filter types. The
+    /// frequency is kept below Nyquist so the filter stays stable at any sample rate.
+    fn omega_alpha(sample_rate: f32, frequency: f32, q: f32) -> (f32, f32) {
+        let frequency = frequency.clamp(1.0, sample_rate * 0.49);
+        let omega = 2.0 * PI * frequency / sample_rate;
+        (omega.cos(), omega.sin() / (2.0 * q))
+    }
+}
+
+/// The state for a single channel's biquad, in transposed direct form II. The state is stored in
+/// double precision for `process_f64()`, and `process()` rounds it to single precision so it gives
+/// exactly the same results as a single precision filter. Switching between the two carries the
+/// state over.
+#[derive(Clone, Copy, Default)]
+pub struct Biquad {
+    s1: f64,
+    s2: f64,
+}
+
+impl Biquad {
+    #[inline(always)]
+    pub fn process(&mut self, coefficients: &BiquadCoefficients, x: f32) -> f32 {
+        let y = coefficients.b0 * x + self.s1 as f32;
+        self.s1 = (coefficients.b1 * x - coefficients.a1 * y + self.s2 as f32) as f64;
+        self.s2 = (coefficients.b2 * x - coefficients.a2 * y) as f64;
+        y
+    }
+
+    /// `process()` in double precision. The coefficients are only widened, the gain comes from
+    /// not rounding the state on every sample, which the recursion would otherwise accumulate.
+    #[inline(always)]
+    pub fn process_f64(&mut self, coefficients: &BiquadCoefficients, x: f64) -> f64 {
+        let y = coefficients.b0 as f64 * x + self.s1;
+        self.s1 = coefficients.b1 as f64 * x - coefficients.a1 as f64 * y + self.s2;
+        self.s2 = coefficients.b2 as f64 * x - coefficients.a2 as f64 * y;
+        y
+    }
+
+    /// `process()`, or `process_f64()` with the input and output converted when
+    /// `double_precision` is set.
+    #[inline(always)]
+    pub fn process_with(
+        &mut self,
+        coefficients: &BiquadCoefficients,
+        x: f32,
+        double_precision: bool,
+    ) -> f32 {
+        if double_precision {
+            self.process_f64(coefficients, x as f64) as f32
+        } else {
+            self.process(coefficients, x)
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,139 @@
+//! Suggesting a threshold from a short capture of the input.
+
+use nih_plug::util::gain_to_db;
+
+/// How much audio a capture covers. This is measured in time rather than in blocks so the
+/// suggestion doesn't depend on the host's buffer size.
+pub const LEARN_CAPTURE_MS: f32 = 2000.0;
+/// The gain reduction the suggested threshold aims for on the loudest peak of the capture.
+pub const LEARN_REDUCTION_DB: f32 = 3.0;
+
+/// Measures the clip stage's input while `learn` is enabled. A capture starts whenever `learn`
+/// gets enabled and only runs once, so after the suggestion is made nothing happens until `learn`
+/// is disabled and enabled again.
+#[derive(Clone, Copy, Default)]
+pub struct ThresholdLearner {
+    /// Whether `learn` was enabled during the last block.
+    armed: bool,
+    /// The number of frames left to capture, or `None` when not capturing.
+    remaining_samples: Option<usize>,
+    peak: f32,
+    sum_squares: f64,
+    num_samples: usize,
+    /// The finished capture's result, until it's taken.
+    suggestion_db: Option<f32>,
+}
+
+impl ThresholdLearner {
+    /// Follow the `learn` parameter at the start of a block, starting a capture of
+    /// `capture_samples` frames when it gets enabled and cancelling it when it gets disabled.
+    pub fn set_armed(&mut self, learn: bool, capture_samples: usize) {
+        if learn && !self.armed {
+            self.remaining_samples = Some(capture_samples.max(1));
+            self.peak = 0.0;
+            self.sum_squares = 0.0;
+            self.num_samples = 0;
+        } else if !learn {
+            self.remaining_samples = None;
+        }
+        self.armed = learn;
+    }
+
+    /// Whether the current frame's samples should be passed to `push()`.
+    #[inline]
+    pub fn is_capturing(&self) -> bool {
+        self.remaining_samples.is_some()
+    }
+
+    /// Measure one of the current frame's samples, with the gain and drive already applied.
+    #[inline]
+    pub fn push(&mut self, sample: f32) {
+        self.peak = self.peak.max(sample.abs());
+        self.sum_squares += (sample as f64) * (sample as f64);
+        self.num_samples += 1;
+    }
+
+    /// Finish the current frame. At the end of the capture this works out the suggestion: the
+    /// threshold that takes `LEARN_REDUCTION_DB` off the loudest peak, but no lower than the
+    /// capture's RMS level so dense material doesn't get clipped all the way down into its body.
+    /// `threshold_offset_db` is what gets added to the threshold before it's turned into a
+    /// ceiling, which the suggestion compensates for.
+    #[inline]
+    pub fn end_frame(&mut self, threshold_offset_db: f32) {
+        let Some(remaining_samples) = &mut self.remaining_samples else {
+            return;
+        };
+        *remaining_samples -= 1;
+        if *remaining_samples > 0 {
+            return;
+        }
+
+        self.remaining_samples = None;
+        // Silence doesn't say anything about where the threshold should go
+        if self.peak > 0.0 {
+            let rms = (self.sum_squares / self.num_samples as f64).sqrt() as f32;
+            let level_db = (gain_to_db(self.peak) - LEARN_REDUCTION_DB).max(gain_to_db(rms));
+            self.suggestion_db = Some(level_db - threshold_offset_db);
+        }
+    }
+
+    /// The threshold in decibels suggested by the last capture, if there's one that hasn't been
+    /// taken yet.
+    pub fn take_suggestion(&mut self) -> Option<f32> {
+        self.suggestion_db.take()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPTURE_SAMPLES: usize = 1000;
+
+    /// Capture `samples`, which should be `CAPTURE_SAMPLES` long, with `threshold_offset_db`.
+    fn capture(samples: impl Iterator<Item = f32>, threshold_offset_db: f32) -> ThresholdLearner {
+        let mut learner = ThresholdLearner::default();
+        learner.set_armed(true, CAPTURE_SAMPLES);
+        for sample in samples {
+            assert!(learner.is_capturing());
+            learner.push(sample);
+            learner.end_frame(threshold_offset_db);
+        }
+        assert!(!learner.is_capturing());
+
+        learner
+    }
+
+    #[test]
+    fn suggests_below_the_peak() {
+        let peak = 0.5;
+        // A single peak over a quiet signal, so the RMS floor doesn't come into play
+        let samples = (0..CAPTURE_SAMPLES).map(|i| if i == 500 { -peak } else { 0.01 });
+        let mut learner = capture(samples, -2.0);
+
+        let expected = gain_to_db(peak) - LEARN_REDUCTION_DB + 2.0;
+        let suggestion = learner.take_suggestion().unwrap();
+        assert!((suggestion - expected).abs() < 1.0e-4, "{suggestion}");
+        assert_eq!(learner.take_suggestion(), None);
+    }
+
+    #[test]
+    fn suggests_no_lower_than_the_rms() {
+        let mut learner = capture(std::iter::repeat_n(0.25, CAPTURE_SAMPLES), 0.0);
+        let suggestion = learner.take_suggestion().unwrap();
+        assert!(
+            (suggestion - gain_to_db(0.25)).abs() < 1.0e-4,
+            "{suggestion}"
+        );
+    }
+
+    #[test]
+    fn silence_suggests_nothing() {
+        let mut learner = capture(std::iter::repeat_n(0.0, CAPTURE_SAMPLES), 0.0);
+        assert_eq!(learner.take_suggestion(), None);
+    }
+}
@@ -1,56 +1,598 @@
-use nih_plug::{prelude::*, util::db_to_gain};
+use atomic_float::AtomicF32;
+use clip::{ClipCurve, ClipSettings, ClipSide, MAX_KNEE_DB, apply_clip, knee_from_db};
+use engine::{
+    ClipEngine, ClipEngineParams, DeltaMode, MAX_FOLD_DRIVE, MAX_LOOKAHEAD_MS, MAX_SHAPE_ORDER,
+    Oversampling, PreFilterMode, StereoMode, ceilings_with_symmetry, saturation_with_character,
+};
+use modulation::ModRate;
+use morph::{MorphSnapshot, NUM_MORPH_TARGETS};
+use nih_plug::wrapper::state::PluginState;
+use nih_plug::{
+    prelude::*,
+    util::{MINUS_INFINITY_DB, db_to_gain, gain_to_db},
+};
+use nih_plug_vizia::ViziaState;
+use oversample::{OsFilter, OsQuality};
+use smoothing::SmoothingMode;
+use state::STATE_VERSION;
 use std::num::NonZeroU32;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+use table::{SharedTransferTable, TransferTable};
+use units::{LevelDisplay, LevelUnit};
+use validate::{ValidatedValue, validate_values};
 
-#[inline(always)]
-fn hard_clip(signal: f32, ceiling: f32) -> f32 {
-    let ceiling = ceiling.max(1.0e-12);
-    signal.clamp(-ceiling, ceiling)
-}
-#[inline(always)]
-fn soft_clip(signal: f32, ceiling: f32) -> f32 {
-    let ceiling = ceiling.max(1.0e-12);
-    let x = (signal / ceiling).tanh();
-    x * ceiling
+mod clip;
+mod crossover;
+mod delay;
+mod denormal;
+mod dither;
+mod editor;
+pub mod engine;
+mod envelope;
+mod filter;
+mod learn;
+mod limiter;
+mod lookahead;
+mod meter;
+mod modulation;
+mod morph;
+pub mod oversample;
+pub mod presets;
+mod smoothing;
+mod state;
+pub mod table;
+mod units;
+pub mod validate;
+
+/// The input range covered by `PluginParams::transfer_curve()`. This goes a bit past full scale
+/// so the clipping at a 0 dB threshold is still visible.
+const TRANSFER_CURVE_RANGE: f32 = 1.5;
+
+/// The range of the main, polarity, and right channel thresholds, in decibels. Most adjustments
+/// happen between -12 and 0 dB, so the taper is centered on -6 dB and gives that region more knob
+/// travel than the extremes. The multiband mode's band thresholds keep a linear range that stops at
+/// 0 dB.
+fn threshold_range() -> FloatRange {
+    FloatRange::SymmetricalSkewed {
+        min: -24.0,
+        max: 12.0,
+        factor: FloatRange::skew_factor(-1.0),
+        center: -6.0,
+    }
 }
 
-#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
-enum ClippingModes {
-    HardClip,
-    SoftClip,
+/// A layout with `num_channels` main inputs and outputs and no sidechain.
+const fn multichannel_layout(num_channels: u32) -> AudioIOLayout {
+    AudioIOLayout {
+        main_input_channels: NonZeroU32::new(num_channels),
+        main_output_channels: NonZeroU32::new(num_channels),
+        ..AudioIOLayout::const_default()
+    }
 }
 
-struct RClip {
+pub struct RClip {
     params: Arc<PluginParams>,
+
+    /// The signal chain, recreated in `initialize()` for the new sample rate and channel count.
+    engine: ClipEngine,
+    /// Whether the plugin is between `initialize()` and `deactivate()`. Hosts call `initialize()`
+    /// and `reset()` again after loading a preset or a session's state into a running plugin,
+    /// and this tells those calls apart from a fresh activation.
+    active: bool,
+    /// Set when `initialize()` kept the running engine after a state change, so the `reset()` that
+    /// follows leaves it running as well.
+    state_reloaded: bool,
+    /// The latency last reported to the host, in samples.
+    latency_samples: u32,
+
+    /// Linear peak levels before and after processing, across all channels. These are written
+    /// once at the end of every `process()` call, so a GUI polling at 30-60 fps always sees the
+    /// most recent block with the meter ballistics already applied.
+    input_peak: Arc<AtomicF32>,
+    output_peak: Arc<AtomicF32>,
+    /// Linear RMS levels before and after processing over the last 300 ms, with the power
+    /// averaged across all channels. These are updated alongside the peak meters, so comparing
+    /// the two shows how much the clipper reduces the crest factor.
+    input_rms: Arc<AtomicF32>,
+    output_rms: Arc<AtomicF32>,
+    /// The output's true peak level in dBTP, measured by interpolating the output to four times
+    /// the sample rate. This catches the inter-sample overs a converter would reconstruct, which
+    /// the sample peak meter misses. It uses the same release as the peak meters.
+    true_peak: Arc<AtomicF32>,
+    /// How much the clip stage is taking off the driven signal, in positive decibels. This uses
+    /// the same cadence and release as the peak meters.
+    gr_meter: Arc<AtomicF32>,
+    /// The fraction of samples over the last 300 ms, across all channels, that went into the clip
+    /// stage above the threshold. With `clip_ratio_oversampled` this is measured at the
+    /// oversampled rate and includes inter-sample overs.
+    clip_ratio: Arc<AtomicF32>,
+    /// The correlation between the left and right output over the last 300 ms, for checking
+    /// whether the clipping hurts the mono compatibility. One is mono, zero is unrelated, and
+    /// negative values partially cancel when summed. Mono layouts always report one.
+    correlation: Arc<AtomicF32>,
+    /// How many NaN or infinite samples the host has sent since the last reset. These are replaced
+    /// with silence before they reach any of the filters.
+    non_finite_samples: Arc<AtomicU32>,
+    /// Lit for a moment whenever the output reaches the ceiling, for an overload indicator.
+    over: Arc<AtomicBool>,
 }
 
 #[derive(Params)]
-struct PluginParams {
+pub struct PluginParams {
+    /// The editor's size, saved with the session.
+    #[persist = "editor-state"]
+    editor_state: Arc<ViziaState>,
+    /// The version of the parameter set, so older sessions can be migrated when they're loaded.
+    /// The key needs to match `state::STATE_VERSION_KEY`.
+    #[persist = "state-version"]
+    state_version: RwLock<u32>,
+    /// The points for `ClipCurve::Table`, saved with the session. Setting new points from the GUI
+    /// or anywhere else swaps them into the running engine between blocks.
+    #[persist = "transfer-table"]
+    pub transfer_table: Arc<SharedTransferTable>,
+
+    /// This keeps its original ID so sessions from before clip curves were consolidated still load.
     #[id = "mode"]
-    pub mode: EnumParam<ClippingModes>,
+    pub curve: EnumParam<ClipCurve>,
+
+    /// Clip both polarities, or leave one of them untouched.
+    #[id = "clip_side"]
+    pub clip_side: EnumParam<ClipSide>,
+
+    /// Only has an effect on stereo layouts.
+    #[id = "stereo_mode"]
+    pub stereo_mode: EnumParam<StereoMode>,
+
+    /// Scales the side signal before it reaches the clip stage, from mono at 0% to twice as wide
+    /// at 200%. Only has an effect on stereo layouts.
+    #[id = "width"]
+    pub width: FloatParam,
 
+    /// Runs the clip stage at a multiple of the sample rate to suppress aliasing. Adds latency.
+    /// The sine folder and the wavefolder always run at 8x or more, so selecting either may
+    /// increase the latency.
+    #[id = "oversampling"]
+    pub oversampling: EnumParam<Oversampling>,
+
+    /// Longer oversampling filters have a steeper cutoff and let less aliasing through, at the
+    /// cost of more CPU and latency.
+    #[id = "os_quality"]
+    pub os_quality: EnumParam<OsQuality>,
+
+    /// Linear phase filters keep the phase intact but add more latency, minimum phase filters
+    /// add almost none but shift the highs in phase.
+    #[id = "os_filter"]
+    pub os_filter: EnumParam<OsFilter>,
+
+    /// Input trim applied before everything else.
     #[id = "gain"]
     pub gain: FloatParam,
 
-    #[id = "threshold"]
+    /// How hard the signal is pushed into the clip stage, on top of `gain`. Use `output_gain` to
+    /// bring the level back down.
+    #[id = "drive"]
+    pub drive: FloatParam,
+
+    /// The clip stage's ceiling relative to full scale. This goes up to +12 dB so the ceiling can
+    /// sit above the nominal input level when driving into it or for parallel clipping. Extending
+    /// the range moved this and the other extended thresholds to new IDs, since hosts store
+    /// automation normalized and would have mapped it onto the new range. Saved sessions are
+    /// migrated to the new IDs with their values unchanged.
+    #[id = "threshold_db"]
     pub threshold: FloatParam,
 
+    /// Rounds off the corner of the hard clipper over this range of input levels around the
+    /// threshold, like a compressor's knee. Zero is a sharp corner. The other curves are soft
+    /// already and ignore this. This replaced a percentage under a different ID, so sessions saved
+    /// before that load with a sharp corner.
+    #[id = "knee_db"]
+    pub knee: FloatParam,
+
+    /// Morphs the hard clipper into the tanh curve at the same threshold, for a continuous range
+    /// from transparent at 0% to heavily saturated at 100%. The other curves ignore this.
+    #[id = "saturation"]
+    pub saturation: FloatParam,
+
+    /// A single control for the hard clipper's tone, from hard and loud at 0% to soft and level
+    /// matched at 100%. This pushes the saturation the rest of the way towards the tanh curve and
+    /// adds back the loudness the softer curve gives up, so the level stays roughly the same while
+    /// the tone changes. The other curves ignore this.
+    #[id = "character"]
+    pub character: FloatParam,
+
+    /// Runs the signal through a tanh saturator before the curve, for the classic saturate then
+    /// clip chain: the saturator rounds off the harmonics on the way up to the threshold and the
+    /// curve holds a firm ceiling at it. `sat_drive` sets how hard the saturator is hit.
+    #[id = "two_stage"]
+    pub two_stage: BoolParam,
+
+    #[id = "sat_drive"]
+    pub sat_drive: FloatParam,
+
+    /// When enabled, both polarities clip at `threshold` and the two asymmetric thresholds below
+    /// are ignored.
+    #[id = "link_thresholds"]
+    pub link_thresholds: BoolParam,
+
+    /// Lowers all thresholds by `gain`, so turning up the gain drives the signal further into a
+    /// ceiling that moves down with it instead of only making everything louder. The drive is not
+    /// included, so it still pushes into a fixed ceiling.
+    #[id = "link_gain_threshold"]
+    pub link_gain_threshold: BoolParam,
+
+    #[id = "threshold_pos_db"]
+    pub threshold_pos: FloatParam,
+
+    #[id = "threshold_neg_db"]
+    pub threshold_neg: FloatParam,
+
+    /// Shifts the clipping from symmetric, which only adds odd harmonics, to asymmetric, which
+    /// adds even harmonics as well. Positive values lower the positive threshold and negative
+    /// values the negative one, by up to 12 dB at the extremes. This stacks with the separate
+    /// polarity thresholds.
+    #[id = "symmetry"]
+    pub symmetry: FloatParam,
+
+    /// When disabled, the second channel (right, or side in mid/side mode) clips at `threshold_r`
+    /// instead of `threshold`. This only applies while the polarity thresholds are linked. Any
+    /// channels beyond the first two always use `threshold`.
+    #[id = "link_channels"]
+    pub link_channels: BoolParam,
+
+    #[id = "threshold_r_db"]
+    pub threshold_r: FloatParam,
+
+    /// Clips both channels of a stereo pair by the same amount, taken from whichever one is being
+    /// clipped harder. This keeps the stereo image from shifting when only one side clips, but the
+    /// quieter channel gets clipped more than it needs to be. Mono and surround layouts still clip
+    /// every channel on its own.
+    #[id = "stereo_link"]
+    pub stereo_link: BoolParam,
+
+    /// How much of the harder clipped channel's gain reduction the stereo link applies to the
+    /// other one, from independent clipping at 0% to fully linked at 100%. In between the stereo
+    /// image shifts a little when only one side clips, in exchange for the quieter channel keeping
+    /// more of its own character.
+    #[id = "link_amount"]
+    pub link_amount: FloatParam,
+
+    /// Lowers the threshold while the sidechain input is active, by up to `sidechain_depth` when
+    /// the sidechain's envelope reaches 0 dBFS. An unconnected sidechain is silent and the
+    /// surround layouts don't have one, so in both cases this falls back to the static threshold.
+    #[id = "sidechain_enable"]
+    pub sidechain_enable: BoolParam,
+
+    #[id = "sidechain_depth"]
+    pub sidechain_depth: FloatParam,
+
+    /// How quickly the threshold follows the sidechain's level going up and coming back down.
+    #[id = "sc_attack_ms"]
+    pub sc_attack_ms: FloatParam,
+
+    #[id = "sc_release_ms"]
+    pub sc_release_ms: FloatParam,
+
+    /// Replaces the output with the sidechain input so it can be auditioned while setting up the
+    /// sidechain. Each output channel plays the sidechain channel with the same index, or the last
+    /// one for sidechains with fewer channels. This only works with a sidechain connected, and
+    /// the output is silent otherwise.
+    #[id = "sc_listen"]
+    pub sc_listen: BoolParam,
+
+    /// Pulses the threshold in time with the host's tempo, dipping it by up to `mod_depth` once
+    /// every `mod_rate`. The LFO follows the host's musical position, so every cycle starts on the
+    /// grid. Hosts that don't report a tempo get 120 BPM, and without a position the LFO keeps
+    /// running freely.
+    #[id = "mod_enable"]
+    pub mod_enable: BoolParam,
+
+    #[id = "mod_rate"]
+    pub mod_rate: EnumParam<ModRate>,
+
+    #[id = "mod_depth"]
+    pub mod_depth: FloatParam,
+
+    /// Lets the threshold follow the input's dynamics like some analog clippers do. While the
+    /// input's level holds steady the threshold rises by up to `pd_amount` so sustained passages
+    /// clip less, and when its peaks jump above their average over `pd_time_ms` the threshold
+    /// drops by up to the same amount so transients get clipped harder.
+    #[id = "program_dependent"]
+    pub program_dependent: BoolParam,
+
+    #[id = "pd_amount"]
+    pub pd_amount: FloatParam,
+
+    /// How long the input's level needs to hold before it counts as sustained.
+    #[id = "pd_time_ms"]
+    pub pd_time_ms: FloatParam,
+
+    /// High-passes the signal going into the clip stage so the low end doesn't dominate the
+    /// clipping. This runs after the lookahead, which still looks at the full-band signal.
+    #[id = "pre_hpf_enable"]
+    pub pre_hpf_enable: BoolParam,
+
+    #[id = "pre_hpf_hz"]
+    pub pre_hpf_hz: FloatParam,
+
+    #[id = "pre_hpf_mode"]
+    pub pre_hpf_mode: EnumParam<PreFilterMode>,
+
+    /// Makes the threshold frequency dependent by tilting the spectrum around 1 kHz on the way
+    /// into the clip stage and tilting it back on the way out. Positive values clip the highs
+    /// earlier than the lows, negative values the other way around. This is a lighter alternative
+    /// to the multiband mode, and at 0 dB/oct it's bypassed entirely.
+    #[id = "tilt"]
+    pub tilt: FloatParam,
+
+    /// Splits the signal into three bands and clips each of them at its own threshold before
+    /// summing them back together. This replaces the regular thresholds in the clip stage, but
+    /// the lookahead still uses those. With all band thresholds at 0 dB, anything below full
+    /// scale comes out with only the crossover's phase shift.
+    #[id = "multiband_enable"]
+    pub multiband_enable: BoolParam,
+
+    #[id = "crossover_lo_hz"]
+    pub crossover_lo_hz: FloatParam,
+
+    #[id = "crossover_hi_hz"]
+    pub crossover_hi_hz: FloatParam,
+
+    #[id = "threshold_low"]
+    pub threshold_low: FloatParam,
+
+    #[id = "threshold_mid"]
+    pub threshold_mid: FloatParam,
+
+    #[id = "threshold_high"]
+    pub threshold_high: FloatParam,
+
+    /// DC offset added before clipping and removed again afterwards so the waveform clips
+    /// asymmetrically, adding even harmonics, while staying centered.
+    #[id = "bias"]
+    pub bias: FloatParam,
+
+    /// How hard the wavefolder curve gets driven. At 0% it only folds what goes past the
+    /// threshold, at 100% the signal is pushed in eight times as hot. Has no effect on the other
+    /// curves.
+    #[id = "fold_amount"]
+    pub fold_amount: FloatParam,
+
+    /// The polynomial curve's exponent, see `clip::polynomial_clip()`. At 1 the curve stays linear
+    /// up to the threshold like the hard clipper, and higher orders bend it over earlier and
+    /// saturate harder. Has no effect on the other curves.
+    #[id = "shape_order"]
+    pub shape_order: FloatParam,
+
+    /// Adds a touch of analog style hysteresis to the clip stage, which lets fast transients
+    /// overshoot slightly and smears the clipping's highest harmonics. See `clip::Hysteresis`.
+    #[id = "analog_mode"]
+    pub analog_mode: BoolParam,
+
+    /// How quickly the transparent curve's gain reduction sets in on a peak and recovers after it.
+    /// Shorter attacks leave less for the hard clip behind the limiter to catch, and longer
+    /// releases distort less but pull down more of what follows a peak. Has no effect on the other
+    /// curves.
+    #[id = "transparent_attack_ms"]
+    pub transparent_attack_ms: FloatParam,
+
+    #[id = "transparent_release_ms"]
+    pub transparent_release_ms: FloatParam,
+
+    /// Lets the clipping relax over this time after a transient instead of letting go at once,
+    /// which softens the trailing edge of clipped transients. Unlike the limiter this only shapes
+    /// the clipped part of the waveform and leaves everything below the threshold alone. At 0 ms
+    /// the curves clip instantly. See `clip::ClipRelease`.
+    #[id = "clip_release_ms"]
+    pub clip_release_ms: FloatParam,
+
+    /// Replaces the drive, threshold, knee, saturation, symmetry, bias, and fold amount with a
+    /// blend between the two stored snapshots, from `morph_a` at 0% `morph` to `morph_b` at 100%.
+    /// The snapshots are stored with `PluginParams::store_morph_a()` and `store_morph_b()`.
+    #[id = "morph_enable"]
+    pub morph_enable: BoolParam,
+
+    #[id = "morph"]
+    pub morph: FloatParam,
+
+    #[nested(id_prefix = "morph_a", group = "Morph A")]
+    pub morph_a: MorphSnapshot,
+
+    #[nested(id_prefix = "morph_b", group = "Morph B")]
+    pub morph_b: MorphSnapshot,
+
+    /// Delays the signal so transients can be ducked into the ceiling before they arrive. Adds
+    /// this much latency.
+    #[id = "lookahead_ms"]
+    pub lookahead_ms: FloatParam,
+
+    /// Runs the signal through a limiter with its threshold 3 dB below the ceiling before it
+    /// reaches the clip stage, so most of the gain reduction is smooth and only the transients
+    /// that get past its 1 ms attack are clipped. The limiter doesn't look ahead, so it adds no
+    /// latency.
+    #[id = "limiter_enable"]
+    pub limiter_enable: BoolParam,
+
+    /// How quickly the limiter's gain recovers after a peak.
+    #[id = "limiter_release_ms"]
+    pub limiter_release_ms: FloatParam,
+
+    #[id = "output_gain"]
+    pub output_gain: FloatParam,
+
+    /// Compensates for the level the gain and drive add, on top of `output_gain`, so switching
+    /// between settings compares them at roughly the same loudness. See `makeup_gain_db()`.
+    #[id = "auto_gain"]
+    pub auto_gain: BoolParam,
+
+    /// Parallel blend between the dry input and the clipped signal. In delta mode this scales the
+    /// difference instead, so the delta output is exactly the part that the mix knob blends in.
+    #[id = "mix"]
+    pub mix: FloatParam,
+
+    /// A brickwall clamp on the final output, after the output gain and the dry/wet mix, so the
+    /// output never exceeds `ceiling_db` regardless of what the stages before it do. With
+    /// oversampling enabled the clip stage's output is also clamped to the ceiling at the
+    /// oversampled rate, which catches inter-sample peaks before they're downsampled. This is not
+    /// applied to the delta signal.
+    #[id = "ceiling_enable"]
+    pub ceiling_enable: BoolParam,
+
+    #[id = "ceiling_db"]
+    pub ceiling_db: FloatParam,
+
+    /// Outputs the difference between the processed and the dry signal instead of the mix,
+    /// scaled by `mix` so it's exactly what the mix knob blends in. At 0% mix this is silent, at
+    /// 50% it's the difference at half its level, and at 100% it's the full difference. The dry
+    /// signal is delayed by the lookahead and the oversampling filters' latency first, so with the
+    /// linear phase filters anything the clip stage leaves alone cancels out.
     #[id = "delta"]
     pub delta: BoolParam,
+
+    /// Boosts the delta signal so the often very quiet difference becomes audible. This has no
+    /// effect outside of delta mode.
+    #[id = "delta_gain"]
+    pub delta_gain: FloatParam,
+
+    /// Whether delta mode compares against the dry input as it came in, or against the dry input
+    /// with the plugin's gain staging applied so only the clipping itself remains.
+    #[id = "delta_mode"]
+    pub delta_mode: EnumParam<DeltaMode>,
+
+    /// Flips the polarity of the final output, including the delta signal. Combined with a dry
+    /// copy of the input this nulls out everything the plugin leaves untouched.
+    #[id = "phase_invert"]
+    pub phase_invert: BoolParam,
+
+    /// Removes the DC offset that asymmetric clipping leaves behind.
+    #[id = "dc_block"]
+    pub dc_block: BoolParam,
+
+    /// A low-pass after the clip stage for taming the high harmonics the clipper adds.
+    #[id = "tone_enable"]
+    pub tone_enable: BoolParam,
+
+    #[id = "tone_hz"]
+    pub tone_hz: FloatParam,
+
+    /// Adds TPDF dither for `dither_bits` to the very end of the chain. This is only useful when
+    /// this is the last plugin before the signal gets reduced to that bit depth, such as when
+    /// exporting to 16-bit files. Anywhere else it just adds noise.
+    #[id = "dither_enable"]
+    pub dither_enable: BoolParam,
+
+    #[id = "dither_bits"]
+    pub dither_bits: IntParam,
+
+    /// Runs the linear filters, that is the tilt, low-cut, DC blocker, and tone filters, in double
+    /// precision so their recursions don't accumulate single precision rounding errors. That only
+    /// matters for long renders of very quiet or very low material, at the cost of a few percent
+    /// more CPU. The clip curves and the oversampling stay in single precision either way. The ID
+    /// is from when this was called high precision, and stays for existing sessions.
+    #[id = "high_precision"]
+    pub f64_filters: BoolParam,
+
+    /// How the level and shape parameters glide to new values. Changes to these take effect with
+    /// the next parameter change.
+    #[id = "smoothing_mode"]
+    pub smoothing_mode: EnumParam<SmoothingMode>,
+
+    #[id = "smoothing_ms"]
+    pub smoothing_ms: FloatParam,
+
+    /// The time it takes for the peak, true peak, and gain reduction meters to fall by 12 dB once
+    /// the signal stops.
+    #[id = "meter_decay_ms"]
+    pub meter_decay_ms: FloatParam,
+
+    /// Stops the peak and true peak meters from decaying at all, so they show the highest level
+    /// since the plugin was last reset. Turning this off and on again clears the held peaks.
+    #[id = "peak_hold"]
+    pub peak_hold: BoolParam,
+
+    /// Measures the clip ratio at the oversampled rate while the oversampling is active, so
+    /// inter-sample peaks above the threshold are counted as well. See `Meters::clip_ratio` for
+    /// how the two measurements differ.
+    #[id = "clip_ratio_oversampled"]
+    pub clip_ratio_oversampled: BoolParam,
+
+    /// Shows the gain and the thresholds in decibels or as a percentage of full scale, and reads
+    /// bare numbers typed into them in that unit. This only affects their text, see the `units`
+    /// module.
+    #[id = "level_unit"]
+    pub level_unit: EnumParam<LevelUnit>,
+
+    /// Listens to the input for `learn::LEARN_CAPTURE_MS` after being enabled and suggests a
+    /// threshold that takes `learn::LEARN_REDUCTION_DB` off the loudest peak, accounting for the
+    /// gain and drive. The editor applies the suggestion with
+    /// `PluginParams::apply_learned_threshold()`, which also disarms this again. Without the editor
+    /// open the suggestion waits until it's opened.
+    #[id = "learn"]
+    pub learn: BoolParam,
+    /// The threshold suggested by the last `learn` capture, or NaN if there is none waiting to be
+    /// applied. The audio thread can't change parameters itself, since nih-plug only allows that
+    /// through a `ParamSetter` from the GUI thread where the host expects parameter gestures to
+    /// come from. So the audio thread only stores its suggestion here, and writing it to
+    /// `threshold` is left to `apply_learned_threshold()`.
+    learned_threshold: Arc<AtomicF32>,
+    /// Set by `reset_meters()` and cleared by the audio thread once it has zeroed the engine's
+    /// meters, for the same reason `learned_threshold` goes through an atomic.
+    meter_reset_requested: Arc<AtomicBool>,
+
+    /// This is marked as the plugin's bypass parameter, so a host's own bypass switch controls
+    /// this parameter instead of competing with it. Toggling it crossfades to the latency
+    /// compensated dry signal. The whole chain keeps running underneath while bypassed, so the
+    /// filters, smoothers, and delay lines are all up to date when it fades back in.
+    #[id = "bypass"]
+    pub bypass: BoolParam,
 }
 
 impl Default for RClip {
     fn default() -> Self {
+        let params = Arc::new(PluginParams::default());
+        let mut engine = ClipEngine::new(44100.0, 0, ClipEngineParams::from(params.as_ref()));
+        engine.set_transfer_table(params.transfer_table.clone());
+
         Self {
-            params: Arc::new(PluginParams::default()),
+            params,
+
+            engine,
+            active: false,
+            state_reloaded: false,
+            latency_samples: 0,
+
+            input_peak: Arc::new(AtomicF32::new(0.0)),
+            output_peak: Arc::new(AtomicF32::new(0.0)),
+            input_rms: Arc::new(AtomicF32::new(0.0)),
+            output_rms: Arc::new(AtomicF32::new(0.0)),
+            true_peak: Arc::new(AtomicF32::new(MINUS_INFINITY_DB)),
+            gr_meter: Arc::new(AtomicF32::new(0.0)),
+            clip_ratio: Arc::new(AtomicF32::new(0.0)),
+            correlation: Arc::new(AtomicF32::new(1.0)),
+            non_finite_samples: Arc::new(AtomicU32::new(0)),
+            over: Arc::new(AtomicBool::new(false)),
         }
     }
 }
 
 impl Default for PluginParams {
     fn default() -> Self {
-        Self {
-            mode: EnumParam::new("Mode", ClippingModes::HardClip),
+        let level_display = LevelDisplay::default();
+        let mut params = Self {
+            editor_state: editor::default_state(),
+            state_version: RwLock::new(STATE_VERSION),
+            transfer_table: Arc::default(),
+
+            curve: EnumParam::new("Curve", ClipCurve::default()),
+            clip_side: EnumParam::new("Clip Side", ClipSide::default()),
+            stereo_mode: EnumParam::new("Stereo Mode", StereoMode::LeftRight),
+            width: FloatParam::new("Width", 1.0, FloatRange::Linear { min: 0.0, max: 2.0 })
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+            oversampling: EnumParam::new("Oversampling", Oversampling::Off),
+            os_quality: EnumParam::new("Oversampling Quality", OsQuality::default()),
+            os_filter: EnumParam::new("Oversampling Filter", OsFilter::default()),
             gain: FloatParam::new(
                 "Gain",
                 0.0,
@@ -60,24 +602,653 @@ impl Default for PluginParams {
                 },
             )
             .with_step_size(0.1)
-            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_value_to_string(level_display.value_to_string())
+            .with_string_to_value(level_display.string_to_value(false)),
+
+            drive: FloatParam::new(
+                "Drive",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 36.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" dB"),
+
+            threshold: FloatParam::new("Threshold", 0.0, threshold_range())
+                .with_step_size(0.1)
+                .with_value_to_string(level_display.value_to_string())
+                .with_string_to_value(level_display.string_to_value(true)),
+
+            knee: FloatParam::new(
+                "Knee",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_KNEE_DB,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" dB"),
+
+            saturation: FloatParam::new(
+                "Saturation",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            character: FloatParam::new("Character", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            two_stage: BoolParam::new("Two Stage", false),
+
+            sat_drive: FloatParam::new(
+                "Saturation Drive",
+                6.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" dB"),
+
+            link_thresholds: BoolParam::new("Link Thresholds", true),
+
+            link_gain_threshold: BoolParam::new("Link Gain to Threshold", false),
+
+            threshold_pos: FloatParam::new("Threshold +", 0.0, threshold_range())
+                .with_step_size(0.1)
+                .with_value_to_string(level_display.value_to_string())
+                .with_string_to_value(level_display.string_to_value(true)),
+
+            threshold_neg: FloatParam::new("Threshold -", 0.0, threshold_range())
+                .with_step_size(0.1)
+                .with_value_to_string(level_display.value_to_string())
+                .with_string_to_value(level_display.string_to_value(true)),
+
+            symmetry: FloatParam::new(
+                "Symmetry",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_step_size(0.01),
+
+            link_channels: BoolParam::new("Link Channels", true),
+
+            threshold_r: FloatParam::new("Threshold R", 0.0, threshold_range())
+                .with_step_size(0.1)
+                .with_value_to_string(level_display.value_to_string())
+                .with_string_to_value(level_display.string_to_value(true)),
+
+            stereo_link: BoolParam::new("Stereo Link", false),
+
+            link_amount: FloatParam::new(
+                "Link Amount",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            sidechain_enable: BoolParam::new("Sidechain", false),
+
+            sidechain_depth: FloatParam::new(
+                "Sidechain Depth",
+                12.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" dB"),
+
+            sc_attack_ms: FloatParam::new(
+                "Sidechain Attack",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 100.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" ms"),
+
+            sc_release_ms: FloatParam::new(
+                "Sidechain Release",
+                100.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 1000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" ms"),
+
+            sc_listen: BoolParam::new("Sidechain Listen", false),
+
+            mod_enable: BoolParam::new("Threshold LFO", false),
+
+            mod_rate: EnumParam::new("LFO Rate", ModRate::default()),
+
+            mod_depth: FloatParam::new(
+                "LFO Depth",
+                6.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_step_size(0.1)
             .with_unit(" dB"),
 
-            threshold: FloatParam::new(
-                "Threshold",
+            program_dependent: BoolParam::new("Program Dependent", false),
+
+            pd_amount: FloatParam::new("PD Amount", 2.0, FloatRange::Linear { min: 0.0, max: 6.0 })
+                .with_step_size(0.1)
+                .with_unit(" dB"),
+
+            pd_time_ms: FloatParam::new(
+                "PD Time",
+                300.0,
+                FloatRange::Skewed {
+                    min: 10.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" ms"),
+
+            pre_hpf_enable: BoolParam::new("Pre HPF", false),
+
+            pre_hpf_hz: FloatParam::new(
+                "Pre HPF Cutoff",
+                80.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            pre_hpf_mode: EnumParam::new("Pre HPF Mode", PreFilterMode::Audio),
+
+            tilt: FloatParam::new(
+                "Tilt",
+                0.0,
+                FloatRange::Linear {
+                    min: -6.0,
+                    max: 6.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" dB/oct"),
+
+            multiband_enable: BoolParam::new("Multiband", false),
+
+            crossover_lo_hz: FloatParam::new(
+                "Low Crossover",
+                200.0,
+                FloatRange::Skewed {
+                    min: 40.0,
+                    max: 1_000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            crossover_hi_hz: FloatParam::new(
+                "High Crossover",
+                3_000.0,
+                FloatRange::Skewed {
+                    min: 1_000.0,
+                    max: 10_000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            threshold_low: FloatParam::new(
+                "Threshold Low",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 0.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_value_to_string(level_display.value_to_string())
+            .with_string_to_value(level_display.string_to_value(true)),
+
+            threshold_mid: FloatParam::new(
+                "Threshold Mid",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 0.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_value_to_string(level_display.value_to_string())
+            .with_string_to_value(level_display.string_to_value(true)),
+
+            threshold_high: FloatParam::new(
+                "Threshold High",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 0.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_value_to_string(level_display.value_to_string())
+            .with_string_to_value(level_display.string_to_value(true)),
+
+            bias: FloatParam::new(
+                "Bias",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_step_size(0.01),
+
+            fold_amount: FloatParam::new(
+                "Fold Amount",
+                0.1,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            shape_order: FloatParam::new(
+                "Shape Order",
+                2.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: MAX_SHAPE_ORDER,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_step_size(0.01),
+
+            analog_mode: BoolParam::new("Analog", false),
+
+            transparent_attack_ms: FloatParam::new(
+                "Transparent Attack",
+                0.5,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 10.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.01)
+            .with_unit(" ms"),
+
+            transparent_release_ms: FloatParam::new(
+                "Transparent Release",
+                40.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" ms"),
+
+            clip_release_ms: FloatParam::new(
+                "Clip Release",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 50.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.01)
+            .with_unit(" ms"),
+
+            morph_enable: BoolParam::new("Morph", false),
+
+            morph: FloatParam::new(
+                "Morph Amount",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            morph_a: MorphSnapshot::new("A", [0.0; NUM_MORPH_TARGETS]),
+            morph_b: MorphSnapshot::new("B", [0.0; NUM_MORPH_TARGETS]),
+
+            lookahead_ms: FloatParam::new(
+                "Lookahead",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_LOOKAHEAD_MS,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" ms"),
+
+            limiter_enable: BoolParam::new("Limiter", false),
+
+            limiter_release_ms: FloatParam::new(
+                "Limiter Release",
+                50.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" ms"),
+
+            output_gain: FloatParam::new(
+                "Output Gain",
                 0.0,
                 FloatRange::Linear {
                     min: -24.0,
+                    max: 24.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" dB"),
+
+            auto_gain: BoolParam::new("Auto Gain", false),
+
+            mix: FloatParam::new("Mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            ceiling_enable: BoolParam::new("Ceiling", false),
+
+            ceiling_db: FloatParam::new(
+                "Ceiling Level",
+                0.0,
+                FloatRange::Linear {
+                    min: -12.0,
                     max: 0.0,
                 },
             )
             .with_step_size(0.1)
-            .with_smoother(SmoothingStyle::Linear(50.0))
             .with_unit(" dB"),
 
             delta: BoolParam::new("Delta", false),
+
+            delta_gain: FloatParam::new(
+                "Delta Gain",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 36.0,
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" dB"),
+            delta_mode: EnumParam::new("Delta Mode", DeltaMode::VsInput),
+
+            phase_invert: BoolParam::new("Invert Phase", false),
+
+            dc_block: BoolParam::new("DC Block", true),
+
+            tone_enable: BoolParam::new("Tone", false),
+
+            tone_hz: FloatParam::new(
+                "Tone Cutoff",
+                20_000.0,
+                FloatRange::Skewed {
+                    min: 2_000.0,
+                    max: 20_000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            dither_enable: BoolParam::new("Dither", false),
+
+            dither_bits: IntParam::new("Dither Bits", 16, IntRange::Linear { min: 8, max: 24 })
+                .with_unit(" bits"),
+
+            f64_filters: BoolParam::new("F64 Filters", false),
+
+            smoothing_mode: EnumParam::new("Smoothing", SmoothingMode::default()),
+
+            smoothing_ms: FloatParam::new(
+                "Smoothing Time",
+                50.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_step_size(0.1)
+            .with_unit(" ms"),
+
+            meter_decay_ms: FloatParam::new(
+                "Meter Decay",
+                300.0,
+                FloatRange::Skewed {
+                    min: 50.0,
+                    max: 5_000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_step_size(1.0)
+            .with_unit(" ms"),
+
+            peak_hold: BoolParam::new("Peak Hold", false),
+
+            clip_ratio_oversampled: BoolParam::new("Oversampled Clip Ratio", false),
+
+            level_unit: EnumParam::new("Level Unit", LevelUnit::default())
+                .with_callback(Arc::new(move |unit| level_display.set(unit))),
+            learn: BoolParam::new("Learn Threshold", false),
+            learned_threshold: Arc::new(AtomicF32::new(f32::NAN)),
+            meter_reset_requested: Arc::new(AtomicBool::new(false)),
+
+            bypass: BoolParam::new("Bypass", false)
+                .with_value_to_string(formatters::v2s_bool_bypass())
+                .with_string_to_value(formatters::s2v_bool_bypass())
+                .make_bypass(),
+        };
+
+        // The snapshots start out at the defaults of the parameters they capture
+        let defaults = params
+            .morph_targets()
+            .map(|target| target.default_normalized_value());
+        params.morph_a = MorphSnapshot::new("A", defaults);
+        params.morph_b = MorphSnapshot::new("B", defaults);
+
+        params
+    }
+}
+
+impl PluginParams {
+    /// The parameters captured by the morph snapshots, in the order `MorphSnapshot` stores them.
+    pub fn morph_targets(&self) -> [&FloatParam; NUM_MORPH_TARGETS] {
+        [
+            &self.drive,
+            &self.threshold,
+            &self.knee,
+            &self.saturation,
+            &self.symmetry,
+            &self.bias,
+            &self.fold_amount,
+        ]
+    }
+
+    /// Store the current values of `morph_targets()` as the snapshot `morph` blends from.
+    pub fn store_morph_a(&self, setter: &ParamSetter) {
+        self.morph_a.store(setter, self.morph_targets());
+    }
+
+    /// Store the current values of `morph_targets()` as the snapshot `morph` blends to.
+    pub fn store_morph_b(&self, setter: &ParamSetter) {
+        self.morph_b.store(setter, self.morph_targets());
+    }
+
+    /// Write the threshold suggested by the last `learn` capture to `threshold` and disarm
+    /// `learn`, returning whether there was a suggestion to apply. This needs to be called from the
+    /// GUI thread, and the editor polls it while it's open.
+    pub fn apply_learned_threshold(&self, setter: &ParamSetter) -> bool {
+        let threshold = self.learned_threshold.swap(f32::NAN, Ordering::Relaxed);
+        if threshold.is_nan() {
+            return false;
+        }
+
+        setter.begin_set_parameter(&self.threshold);
+        setter.set_parameter(&self.threshold, threshold);
+        setter.end_set_parameter(&self.threshold);
+        setter.begin_set_parameter(&self.learn);
+        setter.set_parameter(&self.learn, false);
+        setter.end_set_parameter(&self.learn);
+
+        true
+    }
+
+    /// Set all meters back to their readings for silence, including the held peaks, the clip
+    /// ratio, and the over indicator, without resetting the signal chain. This can be called from any thread, and takes effect with the
+    /// next block the plugin processes.
+    pub fn reset_meters(&self) {
+        self.meter_reset_requested.store(true, Ordering::Relaxed);
+    }
+
+    /// The plain values the plugin would actually use for these `(id, value)` pairs, see
+    /// `validate::validate_values()`.
+    pub fn validate(&self, values: &[(&str, f32)]) -> Result<Vec<ValidatedValue>, String> {
+        validate_values(self, values)
+    }
+
+    /// Sample the clip stage's transfer function for the current parameter values at `n` evenly
+    /// spaced inputs across `±TRANSFER_CURVE_RANGE`, returning `(input, output)` pairs. This only
+    /// covers the waveshaper itself, so the gain staging, lookahead, and filters are left out,
+    /// and the antialiased hard clipper shows up as its plain counterpart.
+    pub fn transfer_curve(&self, n: usize) -> Vec<(f32, f32)> {
+        let symmetry = self.symmetry.value();
+        let threshold_offset_db = if self.link_gain_threshold.value() {
+            -self.gain.value()
+        } else {
+            0.0
+        };
+        let threshold_db = self.threshold.value() + threshold_offset_db;
+        let (ceiling_pos, ceiling_neg) = if self.link_thresholds.value() {
+            ceilings_with_symmetry(threshold_db, threshold_db, symmetry)
+        } else {
+            ceilings_with_symmetry(
+                self.threshold_pos.value() + threshold_offset_db,
+                self.threshold_neg.value() + threshold_offset_db,
+                symmetry,
+            )
+        };
+        let clip_settings = ClipSettings {
+            curve: self.curve.value(),
+            side: self.clip_side.value(),
+            knee: knee_from_db(self.knee.value()),
+            folds: 1.0 + self.fold_amount.value() * (MAX_FOLD_DRIVE - 1.0),
+            order: self.shape_order.value(),
+            saturation: saturation_with_character(self.saturation.value(), self.character.value()),
+            sat_drive: db_to_gain(self.sat_drive.value()),
+            two_stage: if self.two_stage.value() { 1.0 } else { 0.0 },
+            table: &TransferTable::new(&self.transfer_table.points()),
+        };
+
+        (0..n)
+            .map(|i| {
+                let t = if n > 1 {
+                    i as f32 / (n - 1) as f32
+                } else {
+                    0.5
+                };
+                let input = (t * 2.0 - 1.0) * TRANSFER_CURVE_RANGE;
+                (
+                    input,
+                    apply_clip(
+                        &clip_settings,
+                        clip_settings.presaturate(input, ceiling_pos, ceiling_neg),
+                        ceiling_pos,
+                        ceiling_neg,
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+impl RClip {
+    /// The sample rate from the last `initialize()` call. Before the plugin has been initialized
+    /// this is a placeholder of 44.1 kHz.
+    pub fn sample_rate(&self) -> f32 {
+        self.engine.sample_rate()
+    }
+
+    /// The latency last reported to the host, in samples.
+    pub fn latency_samples(&self) -> u32 {
+        self.latency_samples
+    }
+
+    /// Copy the current parameter values into the engine, returning the new latency if it needs
+    /// to be reported to the host.
+    fn update_engine_params(&mut self) -> Option<u32> {
+        self.engine
+            .set_params(ClipEngineParams::from(self.params.as_ref()));
+
+        let latency_samples = self.engine.latency_samples();
+        if latency_samples != self.latency_samples {
+            self.latency_samples = latency_samples;
+            Some(latency_samples)
+        } else {
+            None
         }
     }
+
+    /// Publish the engine's meter readings for the editor.
+    fn store_meters(&self) {
+        let meters = self.engine.meters();
+        self.input_peak.store(meters.input_peak, Ordering::Relaxed);
+        self.output_peak
+            .store(meters.output_peak, Ordering::Relaxed);
+        self.input_rms.store(meters.input_rms, Ordering::Relaxed);
+        self.output_rms.store(meters.output_rms, Ordering::Relaxed);
+        self.true_peak
+            .store(gain_to_db(meters.true_peak), Ordering::Relaxed);
+        self.gr_meter
+            .store(meters.gain_reduction_db, Ordering::Relaxed);
+        self.clip_ratio.store(meters.clip_ratio, Ordering::Relaxed);
+        self.correlation
+            .store(meters.correlation, Ordering::Relaxed);
+        self.non_finite_samples
+            .store(meters.non_finite_samples, Ordering::Relaxed);
+        self.over.store(meters.over, Ordering::Relaxed);
+    }
 }
 
 impl Plugin for RClip {
@@ -91,15 +1262,32 @@ impl Plugin for RClip {
         AudioIOLayout {
             main_input_channels: NonZeroU32::new(2),
             main_output_channels: NonZeroU32::new(2),
-            aux_input_ports: &[],
+            aux_input_ports: &[new_nonzero_u32(2)],
             aux_output_ports: &[],
-            names: PortNames::const_default(),
+            names: PortNames {
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
         },
         AudioIOLayout {
             main_input_channels: NonZeroU32::new(1),
             main_output_channels: NonZeroU32::new(1),
+            aux_input_ports: &[new_nonzero_u32(1)],
+            names: PortNames {
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
             ..AudioIOLayout::const_default()
         },
+        // Every other channel count up to 7.1. Every channel gets its own state and is clipped on
+        // its own, and mid/side processing is only available on the stereo layout. The surround
+        // layouts are what `CLAP_FEATURES` and `VST3_SUBCATEGORIES` advertise as surround support.
+        multichannel_layout(3),
+        multichannel_layout(4),
+        multichannel_layout(5),
+        multichannel_layout(6),
+        multichannel_layout(7),
+        multichannel_layout(8),
     ];
 
     const MIDI_INPUT: MidiConfig = MidiConfig::None;
@@ -111,51 +1299,111 @@ impl Plugin for RClip {
         self.params.clone()
     }
 
+    fn filter_state(state: &mut PluginState) {
+        state::migrate(state);
+    }
+
+    fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        editor::create(
+            self.params.clone(),
+            self.input_peak.clone(),
+            self.output_peak.clone(),
+            self.gr_meter.clone(),
+            self.params.editor_state.clone(),
+        )
+    }
+
     fn initialize(
         &mut self,
-        _audio_io_layout: &AudioIOLayout,
-        _buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
+        let num_channels = audio_io_layout
+            .main_output_channels
+            .map(NonZeroU32::get)
+            .unwrap_or(0) as usize;
+        let params = ClipEngineParams::from(self.params.as_ref());
+        // With the same audio configuration, reinitializing a running plugin means its state was
+        // just replaced. Keeping the engine lets the smoothers glide from their current values to
+        // the new ones instead of everything, including the filters, snapping to them at once.
+        if self.active
+            && self.engine.sample_rate() == buffer_config.sample_rate
+            && self.engine.num_channels() == num_channels
+        {
+            self.engine.set_params(params);
+            self.state_reloaded = true;
+        } else {
+            self.engine = ClipEngine::new(buffer_config.sample_rate, num_channels, params);
+            self.engine
+                .set_transfer_table(self.params.transfer_table.clone());
+        }
+        self.active = true;
+
+        self.latency_samples = self.engine.latency_samples();
+        context.set_latency_samples(self.latency_samples);
+
         true
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        self.engine
+            .set_params(ClipEngineParams::from(self.params.as_ref()));
+        if std::mem::take(&mut self.state_reloaded) {
+            return;
+        }
+        self.engine.reset();
+        self.store_meters();
+    }
 
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        let delta = self.params.delta.value();
-        let mode = self.params.mode.value();
-
-        for channel_samples in buffer.iter_samples() {
-            let gain_db = self.params.gain.smoothed.next();
-            let gain = db_to_gain(gain_db);
+        // Setting a new latency makes the host restart processing so the new delay compensation
+        // takes effect
+        if let Some(latency_samples) = self.update_engine_params() {
+            context.set_latency_samples(latency_samples);
+        }
 
-            let threshold_db = self.params.threshold.smoothed.next();
-            let ceiling = db_to_gain(threshold_db);
+        let transport = context.transport();
+        self.engine
+            .set_transport(transport.tempo, transport.pos_beats());
 
-            for sample in channel_samples {
-                let dry = *sample;
+        if self
+            .params
+            .meter_reset_requested
+            .swap(false, Ordering::Relaxed)
+        {
+            self.engine.reset_meters();
+        }
 
-                let signal = dry * gain;
-                let wet = match mode {
-                    ClippingModes::HardClip => hard_clip(signal, ceiling),
-                    ClippingModes::SoftClip => soft_clip(signal, ceiling),
-                };
-                *sample = if delta { wet - dry } else { wet };
-            }
+        let sidechain = aux
+            .inputs
+            .first()
+            .map_or(&[][..], |buffer| buffer.as_slice_immutable());
+        self.engine
+            .process_block_with_sidechain(buffer.as_slice(), sidechain);
+        self.store_meters();
+        if let Some(threshold) = self.engine.take_learned_threshold() {
+            self.params
+                .learned_threshold
+                .store(threshold, Ordering::Relaxed);
         }
 
-        ProcessStatus::Normal
+        // Offline renders would otherwise cut off whatever is still in the delay lines
+        match self.engine.tail_samples() {
+            0 => ProcessStatus::Normal,
+            tail_samples => ProcessStatus::Tail(tail_samples),
+        }
     }
 
-    // This can be used for cleaning up special resources like socket connections whenever the
-    // plugin is deactivated. Most plugins won't need to do anything here.
-    fn deactivate(&mut self) {}
+    fn deactivate(&mut self) {
+        self.active = false;
+        self.state_reloaded = false;
+    }
 }
 
 impl ClapPlugin for RClip {
@@ -163,19 +1411,118 @@ impl ClapPlugin for RClip {
     const CLAP_DESCRIPTION: Option<&'static str> = Some("A clipping plugin");
     const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
     const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    // The channel configurations here need to match `AUDIO_IO_LAYOUTS`, which covers mono, stereo,
+    // and everything up to 7.1
     const CLAP_FEATURES: &'static [ClapFeature] = &[
         ClapFeature::AudioEffect,
         ClapFeature::Stereo,
         ClapFeature::Mono,
+        ClapFeature::Surround,
         ClapFeature::Utility,
     ];
 }
 
 impl Vst3Plugin for RClip {
     const VST3_CLASS_ID: [u8; 16] = *b"MStecktechPlugin";
-    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
-        &[Vst3SubCategory::Fx, Vst3SubCategory::Tools];
+    // Like `CLAP_FEATURES` these need to match `AUDIO_IO_LAYOUTS`
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[
+        Vst3SubCategory::Fx,
+        Vst3SubCategory::Tools,
+        Vst3SubCategory::Mono,
+        Vst3SubCategory::Stereo,
+        Vst3SubCategory::Surround,
+    ];
 }
 
 nih_export_clap!(RClip);
 nih_export_vst3!(RClip);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default parameters with the curve set to `curve`.
+    fn params_with_curve(curve: ClipCurve) -> PluginParams {
+        PluginParams {
+            curve: EnumParam::new("Curve", curve),
+            ..PluginParams::default()
+        }
+    }
+
+    #[test]
+    fn threshold_range_round_trips() {
+        let range = threshold_range();
+        assert_eq!(range.normalize(-24.0), 0.0);
+        assert_eq!(range.normalize(-6.0), 0.5);
+        assert_eq!(range.normalize(12.0), 1.0);
+
+        let params = PluginParams::default();
+        for value in [-24.0, -12.0, -6.0, 0.0, 12.0] {
+            let plain = range.unnormalize(range.normalize(value));
+            assert!((plain - value).abs() < 1.0e-4, "{value}: {plain}");
+
+            let plain = params
+                .threshold
+                .preview_plain(params.threshold.preview_normalized(value));
+            assert!((plain - value).abs() < 1.0e-4, "{value}: {plain}");
+
+            // The band thresholds stop at 0 dB
+            let plain = params
+                .threshold_low
+                .preview_plain(params.threshold_low.preview_normalized(value));
+            assert!((plain - value.min(0.0)).abs() < 1.0e-4, "{value}: {plain}");
+        }
+    }
+
+    #[test]
+    fn transfer_curve_handles_any_length() {
+        let params = params_with_curve(ClipCurve::Hard);
+        assert!(params.transfer_curve(0).is_empty());
+        // A single point sits in the middle instead of dividing by zero
+        assert_eq!(params.transfer_curve(1), vec![(0.0, 0.0)]);
+
+        let n = 100_001;
+        let transfer_curve = params.transfer_curve(n);
+        assert_eq!(transfer_curve.len(), n);
+        assert_eq!(transfer_curve[0].0, -TRANSFER_CURVE_RANGE);
+        assert_eq!(transfer_curve[n - 1].0, TRANSFER_CURVE_RANGE);
+        let spacing = 2.0 * TRANSFER_CURVE_RANGE / (n - 1) as f32;
+        for pair in transfer_curve.windows(2) {
+            let ((previous, _), (input, output)) = (pair[0], pair[1]);
+            assert!((input - previous - spacing).abs() < 1.0e-5, "{input}");
+            assert!(output.is_finite(), "{input}");
+        }
+    }
+
+    #[test]
+    fn transfer_curves_are_monotonic_and_saturating() {
+        for curve in (0..ClipCurve::variants().len()).map(ClipCurve::from_index) {
+            if matches!(curve, ClipCurve::SineFold | ClipCurve::Wavefold) {
+                continue;
+            }
+
+            // The default threshold puts the ceiling at full scale
+            let transfer_curve = params_with_curve(curve).transfer_curve(301);
+            for pair in transfer_curve.windows(2) {
+                let ((_, previous), (input, output)) = (pair[0], pair[1]);
+                assert!(output >= previous, "{curve:?} falls at {input}");
+                assert!(
+                    output.abs() <= 1.0,
+                    "{curve:?} exceeds the ceiling at {input}"
+                );
+            }
+            // Saturating curves never get steeper on their way up to the ceiling
+            let positive: Vec<_> = transfer_curve
+                .into_iter()
+                .filter(|&(input, _)| input >= 0.0)
+                .collect();
+            let slopes: Vec<_> = positive
+                .windows(2)
+                .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+                .collect();
+            for (pair, (input, _)) in slopes.windows(2).zip(&positive[1..]) {
+                assert!(pair[1] <= pair[0] + 1.0e-3, "{curve:?} steepens at {input}");
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Feed-forward limiter riding in front of the clipper.
+//!
+//! The limiter pulls the signal's envelope down to a threshold a few decibels below the ceiling,
+//! so most of the level reduction happens as a smooth gain change instead of distortion. Its
+//! attack isn't instant, which lets the leading edges of transients slip past and into the clip
+//! stage, where the hard clip catches them. It doesn't look ahead, so it adds no latency.
+
+use nih_plug::util::db_to_gain;
+
+use crate::envelope::{EnvelopeCoefficients, EnvelopeFollower};
+
+/// How far below the ceiling the limiter's threshold sits.
+pub const LIMITER_HEADROOM_DB: f32 = -3.0;
+/// The limiter's fixed attack time. Anything faster than this is left to the clipper.
+pub const LIMITER_ATTACK_MS: f32 = 1.0;
+
+#[derive(Clone, Copy)]
+pub struct Limiter {
+    envelope: EnvelopeFollower,
+    /// The gain applied to the last sample `process()` returned.
+    gain: f32,
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self {
+            envelope: EnvelopeFollower::default(),
+            gain: 1.0,
+        }
+    }
+}
+
+impl Limiter {
+    /// The gain applied to the sample last returned by `process()`, for metering.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Apply the limiter's gain to `sample`, with `amount` fading it in from unity gain at zero
+    /// to the full gain reduction at one. The envelope keeps following the signal at zero so
+    /// enabling the limiter doesn't start from stale state. Positive and negative excursions are
+    /// measured against their own ceiling.
+    #[inline]
+    pub fn process(
+        &mut self,
+        coefficients: &EnvelopeCoefficients,
+        sample: f32,
+        ceiling_pos: f32,
+        ceiling_neg: f32,
+        amount: f32,
+    ) -> f32 {
+        let ceiling = if sample >= 0.0 {
+            ceiling_pos
+        } else {
+            ceiling_neg
+        };
+        let threshold = ceiling * db_to_gain(LIMITER_HEADROOM_DB);
+
+        let envelope = self.envelope.process(coefficients, sample);
+        let limiter_gain = if envelope > threshold {
+            threshold / envelope
+        } else {
+            1.0
+        };
+        self.gain = 1.0 - amount * (1.0 - limiter_gain);
+
+        sample * self.gain
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
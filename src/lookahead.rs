@@ -0,0 +1,132 @@
+//! Lookahead gain riding in front of the clipper.
+//!
+//! The signal is delayed by the lookahead time while the gain needed to bring each incoming
+//! sample down to the ceiling is computed ahead of it. Taking the minimum of that gain over the
+//! lookahead window and then averaging it over the same window gives a smooth ramp that reaches
+//! the required gain right as the peak comes out of the delay line, so transients get pulled into
+//! the ceiling instead of being flat-topped.
+
+use crate::delay::DelayLine;
+
+pub struct Lookahead {
+    /// The current lookahead time in samples. Changing this resets the state.
+    length: usize,
+    delay: DelayLine,
+    window_min: SlidingMin,
+    /// The gain reduction (one minus the window minimum) for the last `length` samples, averaged
+    /// to smooth out the steps. Storing the reduction means the zeroed delay line starts out at
+    /// unity gain.
+    reductions: DelayLine,
+    reduction_sum: f64,
+    /// The gain applied to the last sample `process()` returned.
+    gain: f32,
+}
+
+impl Lookahead {
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            length: 0,
+            delay: DelayLine::new(max_length),
+            window_min: SlidingMin::new(max_length + 1),
+            reductions: DelayLine::new(max_length),
+            reduction_sum: 0.0,
+            gain: 1.0,
+        }
+    }
+
+    /// Change the lookahead time. This clears the state when the length actually changes, which
+    /// coincides with a latency change and thus a restart in the host.
+    pub fn set_length(&mut self, length: usize) {
+        let length = length.min(self.delay.max_delay());
+        if length != self.length {
+            self.length = length;
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.delay.reset();
+        self.window_min.reset();
+        self.reductions.reset();
+        self.reduction_sum = 0.0;
+        self.gain = 1.0;
+    }
+
+    /// The gain applied to the sample last returned by `process()`, for metering.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Push a new sample and return the sample from `length` samples ago with the lookahead gain
+    /// applied. Positive and negative excursions are measured against their own ceiling.
+    #[inline]
+    pub fn process(&mut self, sample: f32, ceiling_pos: f32, ceiling_neg: f32) -> f32 {
+        if self.length == 0 {
+            return sample;
+        }
+
+        let ceiling = if sample >= 0.0 {
+            ceiling_pos
+        } else {
+            ceiling_neg
+        };
+        let required_gain = if sample.abs() > ceiling {
+            ceiling / sample.abs()
+        } else {
+            1.0
+        };
+
+        let reduction = 1.0 - self.window_min.push(required_gain, self.length + 1);
+        let oldest_reduction = self.reductions.process(reduction, self.length);
+        self.reduction_sum += reduction as f64 - oldest_reduction as f64;
+        self.gain = (1.0 - (self.reduction_sum / self.length as f64) as f32).clamp(0.0, 1.0);
+
+        self.delay.process(sample, self.length) * self.gain
+    }
+}
+
+/// Tracks the minimum of the most recent values in constant amortized time.
+struct SlidingMin {
+    /// A ring buffer deque of `(index, value)` pairs with increasing values from front to back.
+    entries: Vec<(u64, f32)>,
+    head: usize,
+    len: usize,
+    index: u64,
+}
+
+impl SlidingMin {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: vec![(0, 0.0); capacity],
+            head: 0,
+            len: 0,
+            index: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.index = 0;
+    }
+
+    /// Add a value and return the minimum of the last `window` values, including this one.
+    #[inline]
+    fn push(&mut self, value: f32, window: usize) -> f32 {
+        let capacity = self.entries.len();
+        let window = window.min(capacity) as u64;
+
+        while self.len > 0 && self.entries[(self.head + self.len - 1) % capacity].1 >= value {
+            self.len -= 1;
+        }
+        while self.len > 0 && self.entries[self.head].0 + window <= self.index {
+            self.head = (self.head + 1) % capacity;
+            self.len -= 1;
+        }
+        self.entries[(self.head + self.len) % capacity] = (self.index, value);
+        self.len += 1;
+        self.index += 1;
+
+        self.entries[self.head].1
+    }
+}
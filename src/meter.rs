@@ -0,0 +1,51 @@
+//! Level measurement for the meters.
+
+use crate::delay::DelayLine;
+
+/// The mean square level over a sliding window, kept as a running sum so every sample costs the
+/// same regardless of the window length.
+pub struct RmsWindow {
+    length: usize,
+    squares: DelayLine,
+    sum: f64,
+}
+
+impl RmsWindow {
+    pub fn new(length: usize) -> Self {
+        let length = length.max(1);
+        Self {
+            length,
+            squares: DelayLine::new(length),
+            sum: 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn push(&mut self, sample: f32) {
+        self.push_square(sample * sample);
+    }
+
+    /// Push a value that's already squared, or that should be averaged as is.
+    #[inline]
+    pub fn push_square(&mut self, square: f32) {
+        let oldest = self.squares.process(square, self.length);
+        self.sum += square as f64 - oldest as f64;
+    }
+
+    /// The mean of the squared samples in the window. Rounding errors in the running sum can make
+    /// this dip just below zero during silence, so it's clamped.
+    pub fn mean_square(&self) -> f32 {
+        (self.sum / self.length as f64).max(0.0) as f32
+    }
+
+    /// The mean of the values in the window without the clamping, for values from
+    /// `push_square()` that can be negative.
+    pub fn mean(&self) -> f32 {
+        (self.sum / self.length as f64) as f32
+    }
+
+    pub fn reset(&mut self) {
+        self.squares.reset();
+        self.sum = 0.0;
+    }
+}
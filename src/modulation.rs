@@ -0,0 +1,78 @@
+//! A tempo synced LFO for pulsing the clip threshold in time with the host.
+
+use nih_plug::prelude::*;
+
+/// The tempo the LFO runs at when the host doesn't report one.
+pub const FALLBACK_TEMPO_BPM: f64 = 120.0;
+
+/// The length of one LFO cycle as a note value. New variants need to be added at the end so
+/// existing sessions keep their selection.
+#[derive(Enum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ModRate {
+    #[name = "1/1"]
+    Whole,
+    #[name = "1/2"]
+    Half,
+    #[default]
+    #[name = "1/4"]
+    Quarter,
+    #[name = "1/8"]
+    Eighth,
+    #[name = "1/16"]
+    Sixteenth,
+    #[name = "1/32"]
+    ThirtySecond,
+    #[name = "1/4 T"]
+    QuarterTriplet,
+    #[name = "1/8 T"]
+    EighthTriplet,
+    #[name = "1/16 T"]
+    SixteenthTriplet,
+}
+
+impl ModRate {
+    /// The cycle length in quarter notes, which is what hosts count their beats in.
+    pub fn cycle_beats(self) -> f64 {
+        match self {
+            ModRate::Whole => 4.0,
+            ModRate::Half => 2.0,
+            ModRate::Quarter => 1.0,
+            ModRate::Eighth => 0.5,
+            ModRate::Sixteenth => 0.25,
+            ModRate::ThirtySecond => 0.125,
+            ModRate::QuarterTriplet => 2.0 / 3.0,
+            ModRate::EighthTriplet => 1.0 / 3.0,
+            ModRate::SixteenthTriplet => 1.0 / 6.0,
+        }
+    }
+}
+
+/// A raised cosine LFO whose phase is derived from a position in beats. Whenever the host reports
+/// its musical position the LFO jumps to it, so the modulation stays in time across loops,
+/// relocations, and stops. Without a position it keeps running from where it was at the last
+/// reported tempo.
+#[derive(Clone, Copy, Default)]
+pub struct TempoLfo {
+    position_beats: f64,
+}
+
+impl TempoLfo {
+    /// Align the LFO with the host's position at the start of a block.
+    pub fn sync(&mut self, position_beats: f64) {
+        self.position_beats = position_beats;
+    }
+
+    /// The LFO's value for the current sample, going from zero at the start of every cycle up to
+    /// one halfway through and back down again, before advancing by `beats_per_sample`.
+    #[inline]
+    pub fn next(&mut self, cycle_beats: f64, beats_per_sample: f64) -> f32 {
+        let phase = (self.position_beats / cycle_beats).rem_euclid(1.0);
+        self.position_beats += beats_per_sample;
+
+        (0.5 - 0.5 * (phase * std::f64::consts::TAU).cos()) as f32
+    }
+
+    pub fn reset(&mut self) {
+        self.position_beats = 0.0;
+    }
+}
@@ -0,0 +1,100 @@
+//! Two snapshots of the clip stage's shape that the `morph` parameter blends between.
+
+use nih_plug::prelude::*;
+
+/// The number of parameters a `MorphSnapshot` captures, see `PluginParams::morph_targets()`.
+pub const NUM_MORPH_TARGETS: usize = 7;
+
+/// The clip stage's continuous shape parameters at the time the snapshot was stored. The values
+/// are normalized so each snapshot parameter can hold anything its source parameter can, and they
+/// are hidden from the host since they only change through `PluginParams::store_morph_a()` and
+/// `PluginParams::store_morph_b()`. Being parameters they're saved with the rest of the state.
+#[derive(Params)]
+pub struct MorphSnapshot {
+    #[id = "drive"]
+    pub drive: FloatParam,
+    #[id = "threshold"]
+    pub threshold: FloatParam,
+    #[id = "knee"]
+    pub knee: FloatParam,
+    #[id = "saturation"]
+    pub saturation: FloatParam,
+    #[id = "symmetry"]
+    pub symmetry: FloatParam,
+    #[id = "bias"]
+    pub bias: FloatParam,
+    #[id = "fold_amount"]
+    pub fold_amount: FloatParam,
+}
+
+impl MorphSnapshot {
+    /// A snapshot named `label` holding these normalized values, in the order of
+    /// `PluginParams::morph_targets()`.
+    pub fn new(label: &str, normalized_values: [f32; NUM_MORPH_TARGETS]) -> Self {
+        let [
+            drive,
+            threshold,
+            knee,
+            saturation,
+            symmetry,
+            bias,
+            fold_amount,
+        ] = normalized_values;
+        let slot = |name: &str, value: f32| {
+            FloatParam::new(
+                format!("Morph {label} {name}"),
+                value,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .hide()
+        };
+
+        Self {
+            drive: slot("Drive", drive),
+            threshold: slot("Threshold", threshold),
+            knee: slot("Knee", knee),
+            saturation: slot("Saturation", saturation),
+            symmetry: slot("Symmetry", symmetry),
+            bias: slot("Bias", bias),
+            fold_amount: slot("Fold Amount", fold_amount),
+        }
+    }
+
+    /// The snapshot's parameters in the order of `PluginParams::morph_targets()`.
+    pub fn slots(&self) -> [&FloatParam; NUM_MORPH_TARGETS] {
+        [
+            &self.drive,
+            &self.threshold,
+            &self.knee,
+            &self.saturation,
+            &self.symmetry,
+            &self.bias,
+            &self.fold_amount,
+        ]
+    }
+
+    /// Capture the targets' current values through `setter`, so the host sees the change.
+    pub fn store(&self, setter: &ParamSetter, targets: [&FloatParam; NUM_MORPH_TARGETS]) {
+        for (slot, target) in self.slots().into_iter().zip(targets) {
+            setter.begin_set_parameter(slot);
+            setter.set_parameter_normalized(slot, target.unmodulated_normalized_value());
+            setter.end_set_parameter(slot);
+        }
+    }
+}
+
+/// The targets' plain values blended from snapshot `a` at an `amount` of zero to `b` at one. The
+/// blend happens between the plain values, so a skewed taper doesn't bend the path between them.
+pub fn blend(
+    targets: [&FloatParam; NUM_MORPH_TARGETS],
+    a: &MorphSnapshot,
+    b: &MorphSnapshot,
+    amount: f32,
+) -> [f32; NUM_MORPH_TARGETS] {
+    let (a, b) = (a.slots(), b.slots());
+    std::array::from_fn(|i| {
+        let from = targets[i].preview_plain(a[i].value());
+        let to = targets[i].preview_plain(b[i].value());
+        from + amount * (to - from)
+    })
+}
@@ -0,0 +1,888 @@
+//! Polyphase oversampling for the clip stage, with either linear phase FIR or minimum phase IIR
+//! filters.
+//!
+//! Every 2x stage uses a half-band low-pass for interpolation and decimation. Stages are cascaded
+//! for the higher factors, and the later stages get away with much shallower filters because
+//! their images sit further away from the audio band. Both filter types implement
+//! `HalfBandFilter`, and `Oversampler` runs a cascade of either.
+//!
+//! The FIR stages use the same linear phase kernel in both directions, which delays the signal
+//! by half the kernel's length but leaves the phase alone. The IIR stages are pairs of allpass
+//! chains that only delay the signal by a few samples, at the cost of some phase shift towards
+//! the top of the audio band.
+//!
+//! The stereo stages run two channels through the same filters. The FIR stages keep their history
+//! interleaved, so every tap is applied to both channels with a single SIMD multiply-add where
+//! the target supports it.
+
+use nih_plug::prelude::*;
+use std::f64::consts::PI;
+
+/// The Kaiser window's beta, good for roughly 90 dB of stopband attenuation.
+const KAISER_BETA: f64 = 8.96;
+/// The stopband attenuation the IIR stages are designed for, to match the FIR kernels.
+const IIR_ATTENUATION_DB: f64 = 90.0;
+/// The number of 2x stages an `Oversampler` has, for up to 8x oversampling.
+const NUM_STAGES: usize = 3;
+/// An upper bound for how far the upsampled signal's peaks can exceed the input's, for every
+/// filter, quality, and factor. Any one output sample is a weighted sum of past inputs, so this is
+/// the largest sum of absolute weights over the interpolator's output phases. The minimum phase
+/// filters ring the most at 4.7.
+pub const MAX_UPSAMPLING_GAIN: f32 = 5.0;
+/// How many base rate samples it takes for every interpolator's impulse response to decay to
+/// nothing. The minimum phase filters never quite get there, but by this point they're at
+/// -260 dB.
+pub const UPSAMPLING_MEMORY_SAMPLES: usize = 1024;
+
+/// Which kind of filter the oversampling stages use. New variants need to be added at the end so
+/// existing sessions keep their selection.
+#[derive(Enum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OsFilter {
+    /// No phase distortion, but more latency. Better for mastering.
+    #[default]
+    #[name = "Linear Phase"]
+    LinearPhase,
+    /// Close to no latency, but the highs get shifted in phase. Better for tracking.
+    #[name = "Minimum Phase"]
+    MinimumPhase,
+}
+
+/// Trades CPU and latency for a steeper anti-aliasing filter. New variants need to be added at the
+/// end so existing sessions keep their selection.
+#[derive(Enum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OsQuality {
+    #[name = "Eco"]
+    Eco,
+    #[default]
+    #[name = "Normal"]
+    Normal,
+    #[name = "High"]
+    High,
+}
+
+impl OsQuality {
+    /// FIR kernel lengths for the 2x, 4x and 8x stages. The round trip through stage `n` delays
+    /// the signal by `(len - 1) / 2^n` samples at the base rate, and the lengths are chosen so
+    /// that this is always a whole number of samples.
+    pub fn kernel_lengths(self) -> [usize; 3] {
+        match self {
+            OsQuality::Eco => [31, 13, 9],
+            OsQuality::Normal => [59, 21, 17],
+            OsQuality::High => [119, 41, 25],
+        }
+    }
+
+    /// The IIR filters' transition band widths for the 2x, 4x and 8x stages, relative to each
+    /// stage's higher rate. Narrower transitions need more allpass sections.
+    pub fn iir_transitions(self) -> [f64; 3] {
+        match self {
+            OsQuality::Eco => [0.1, 0.25, 0.3],
+            OsQuality::Normal => [0.05, 0.2, 0.25],
+            OsQuality::High => [0.025, 0.15, 0.2],
+        }
+    }
+}
+
+/// One 2x stage of an oversampler, interpolating on the way up and decimating on the way down.
+pub trait HalfBandFilter {
+    /// One sample for every channel the stage filters.
+    type Frame: Copy;
+
+    /// Design the stage at index `stage` of an oversampler with this quality, counting from the
+    /// 2x stage.
+    fn new(quality: OsQuality, stage: usize) -> Self;
+    /// Turn one input frame into two frames at twice the rate.
+    fn upsample(&mut self, frame: Self::Frame) -> [Self::Frame; 2];
+    /// Turn two frames at twice the rate back into one frame at the input rate.
+    fn downsample(&mut self, frames: [Self::Frame; 2]) -> Self::Frame;
+    /// The delay of a round trip through this stage at low frequencies, in samples at the stage's
+    /// input rate.
+    fn latency(&self) -> f64;
+    fn reset(&mut self);
+}
+
+/// Oversamples by `2^num_stages`, runs a nonlinearity at the higher rate, and decimates the
+/// result back to the base rate.
+pub struct Oversampler<S> {
+    stages: Vec<S>,
+}
+
+impl<S: HalfBandFilter> Oversampler<S> {
+    /// Allocate the filter state for every stage up front so the oversampling factor can change
+    /// during playback without allocating.
+    pub fn new(quality: OsQuality) -> Self {
+        Self {
+            stages: (0..NUM_STAGES)
+                .map(|stage| S::new(quality, stage))
+                .collect(),
+        }
+    }
+
+    /// The delay in base rate samples introduced by running through the first `num_stages`
+    /// stages. This is only a whole number for the FIR stages.
+    pub fn latency(&self, num_stages: usize) -> f64 {
+        self.stages[..num_stages]
+            .iter()
+            .enumerate()
+            .map(|(stage_idx, stage)| stage.latency() / (1 << stage_idx) as f64)
+            .sum()
+    }
+
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.reset();
+        }
+    }
+
+    /// Upsample `frame` through the first `num_stages` stages, call `f` on every resulting
+    /// frame, and return the decimated output. With zero stages this simply calls `f` once.
+    #[inline]
+    pub fn process(
+        &mut self,
+        num_stages: usize,
+        frame: S::Frame,
+        mut f: impl FnMut(S::Frame) -> S::Frame,
+    ) -> S::Frame {
+        process_stages(&mut self.stages[..num_stages], frame, &mut f)
+    }
+
+    /// Only interpolate `frame` through the first `num_stages` stages and call `f` on every
+    /// resulting frame, for measuring the reconstructed signal without going back down.
+    #[inline]
+    pub fn upsample(&mut self, num_stages: usize, frame: S::Frame, mut f: impl FnMut(S::Frame)) {
+        upsample_stages(&mut self.stages[..num_stages], frame, &mut f)
+    }
+}
+
+fn upsample_stages<S: HalfBandFilter>(
+    stages: &mut [S],
+    frame: S::Frame,
+    f: &mut impl FnMut(S::Frame),
+) {
+    match stages.split_first_mut() {
+        None => f(frame),
+        Some((stage, inner)) => {
+            let [even, odd] = stage.upsample(frame);
+            upsample_stages(inner, even, f);
+            upsample_stages(inner, odd, f);
+        }
+    }
+}
+
+fn process_stages<S: HalfBandFilter>(
+    stages: &mut [S],
+    frame: S::Frame,
+    f: &mut impl FnMut(S::Frame) -> S::Frame,
+) -> S::Frame {
+    match stages.split_first_mut() {
+        None => f(frame),
+        Some((stage, inner)) => {
+            let [even, odd] = stage.upsample(frame);
+            let even = process_stages(inner, even, f);
+            let odd = process_stages(inner, odd, f);
+
+            stage.downsample([even, odd])
+        }
+    }
+}
+
+/// An `Oversampler` for every combination of `OsFilter` and `OsQuality`, so both can change
+/// during playback without allocating. `F` and `I` are the FIR and IIR stages for the same kind of
+/// frame.
+pub struct OversamplerBank<F, I> {
+    /// Indexed by `OsQuality as usize`.
+    fir: Vec<Oversampler<F>>,
+    iir: Vec<Oversampler<I>>,
+}
+
+/// Oversamplers for a single channel.
+pub type MonoOversamplers = OversamplerBank<FirStage, IirStage>;
+/// Oversamplers for a pair of channels processed in lockstep. Up to rounding, the output is the
+/// same as running each channel through its own `MonoOversamplers`.
+pub type StereoOversamplers = OversamplerBank<StereoFirStage, StereoIirStage>;
+
+impl<F, I> Default for OversamplerBank<F, I>
+where
+    F: HalfBandFilter,
+    I: HalfBandFilter<Frame = F::Frame>,
+{
+    fn default() -> Self {
+        let qualities = [OsQuality::Eco, OsQuality::Normal, OsQuality::High];
+
+        Self {
+            fir: qualities.into_iter().map(Oversampler::new).collect(),
+            iir: qualities.into_iter().map(Oversampler::new).collect(),
+        }
+    }
+}
+
+impl<F, I> OversamplerBank<F, I>
+where
+    F: HalfBandFilter,
+    I: HalfBandFilter<Frame = F::Frame>,
+{
+    /// The delay in base rate samples introduced by running through `num_stages` stages with
+    /// these filters, rounded to the nearest sample for the IIR filters.
+    pub fn latency(&self, filter: OsFilter, quality: OsQuality, num_stages: usize) -> u32 {
+        match filter {
+            OsFilter::LinearPhase => self.fir[quality as usize].latency(num_stages),
+            OsFilter::MinimumPhase => self.iir[quality as usize].latency(num_stages),
+        }
+        .round() as u32
+    }
+
+    /// The longest delay any combination of filter, quality, and oversampling factor can add.
+    pub fn max_latency(&self) -> u32 {
+        let fir = self
+            .fir
+            .iter()
+            .map(|oversampler| oversampler.latency(NUM_STAGES));
+        let iir = self
+            .iir
+            .iter()
+            .map(|oversampler| oversampler.latency(NUM_STAGES));
+
+        fir.chain(iir).fold(0.0, f64::max).round() as u32
+    }
+
+    /// Clear the state of the filters with these settings.
+    pub fn reset_filter(&mut self, filter: OsFilter, quality: OsQuality) {
+        match filter {
+            OsFilter::LinearPhase => self.fir[quality as usize].reset(),
+            OsFilter::MinimumPhase => self.iir[quality as usize].reset(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for oversampler in &mut self.fir {
+            oversampler.reset();
+        }
+        for oversampler in &mut self.iir {
+            oversampler.reset();
+        }
+    }
+
+    /// `Oversampler::process()` with the oversampler for these settings.
+    #[inline]
+    pub fn process(
+        &mut self,
+        filter: OsFilter,
+        quality: OsQuality,
+        num_stages: usize,
+        frame: F::Frame,
+        f: impl FnMut(F::Frame) -> F::Frame,
+    ) -> F::Frame {
+        match filter {
+            OsFilter::LinearPhase => self.fir[quality as usize].process(num_stages, frame, f),
+            OsFilter::MinimumPhase => self.iir[quality as usize].process(num_stages, frame, f),
+        }
+    }
+}
+
+/// A delay line stored twice in a row, so the most recent `len` samples can always be read as a
+/// single contiguous slice starting with the newest sample.
+struct History {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl History {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len * 2],
+            pos: 0,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, sample: f32) {
+        let len = self.buffer.len() / 2;
+        self.pos = if self.pos == 0 { len - 1 } else { self.pos - 1 };
+        self.buffer[self.pos] = sample;
+        self.buffer[self.pos + len] = sample;
+    }
+
+    /// The stored samples, newest first.
+    #[inline]
+    fn samples(&self) -> &[f32] {
+        let len = self.buffer.len() / 2;
+        &self.buffer[self.pos..self.pos + len]
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.pos = 0;
+    }
+}
+
+/// `History` for stereo frames, stored as interleaved left and right samples.
+struct StereoHistory {
+    buffer: Vec<f32>,
+    /// The position of the newest frame, in frames.
+    pos: usize,
+}
+
+impl StereoHistory {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len * 4],
+            pos: 0,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, frame: [f32; 2]) {
+        let len = self.buffer.len() / 4;
+        self.pos = if self.pos == 0 { len - 1 } else { self.pos - 1 };
+        self.buffer[self.pos * 2..self.pos * 2 + 2].copy_from_slice(&frame);
+        self.buffer[(self.pos + len) * 2..(self.pos + len) * 2 + 2].copy_from_slice(&frame);
+    }
+
+    /// The stored frames, newest first, as interleaved samples.
+    #[inline]
+    fn samples(&self) -> &[f32] {
+        let len = self.buffer.len() / 4;
+        &self.buffer[self.pos * 2..(self.pos + len) * 2]
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.pos = 0;
+    }
+}
+
+/// A linear phase FIR half-band stage with a Kaiser windowed sinc kernel.
+pub struct FirStage {
+    /// The kernel split into its even and odd taps for the polyphase interpolator, with the
+    /// interpolator's gain of two already applied.
+    even_taps: Vec<f32>,
+    odd_taps: Vec<f32>,
+    /// The full kernel for the decimator.
+    kernel: Vec<f32>,
+
+    up_history: History,
+    down_history: History,
+}
+
+impl FirStage {
+    fn with_length(len: usize) -> Self {
+        let kernel = half_band_kernel(len);
+        let even_taps: Vec<f32> = kernel.iter().step_by(2).map(|tap| tap * 2.0).collect();
+        let odd_taps: Vec<f32> = kernel
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|tap| tap * 2.0)
+            .collect();
+
+        Self {
+            up_history: History::new(even_taps.len()),
+            down_history: History::new(len),
+            even_taps,
+            odd_taps,
+            kernel,
+        }
+    }
+}
+
+impl HalfBandFilter for FirStage {
+    type Frame = f32;
+
+    /// See `OsQuality::kernel_lengths()` for the constraints on the kernel lengths.
+    fn new(quality: OsQuality, stage: usize) -> Self {
+        Self::with_length(quality.kernel_lengths()[stage])
+    }
+
+    #[inline]
+    fn upsample(&mut self, sample: f32) -> [f32; 2] {
+        self.up_history.push(sample);
+        let history = self.up_history.samples();
+
+        [dot(&self.even_taps, history), dot(&self.odd_taps, history)]
+    }
+
+    /// Only the first of every two decimator inputs lines up with an output sample.
+    #[inline]
+    fn downsample(&mut self, [even, odd]: [f32; 2]) -> f32 {
+        self.down_history.push(even);
+        let output = dot(&self.kernel, self.down_history.samples());
+        self.down_history.push(odd);
+
+        output
+    }
+
+    fn latency(&self) -> f64 {
+        (self.kernel.len() - 1) as f64 / 2.0
+    }
+
+    fn reset(&mut self) {
+        self.up_history.reset();
+        self.down_history.reset();
+    }
+}
+
+/// `FirStage` for stereo frames. The taps are stored with every coefficient repeated, so they
+/// line up with the interleaved history.
+pub struct StereoFirStage {
+    even_taps: Vec<f32>,
+    odd_taps: Vec<f32>,
+    kernel: Vec<f32>,
+
+    up_history: StereoHistory,
+    down_history: StereoHistory,
+}
+
+impl HalfBandFilter for StereoFirStage {
+    type Frame = [f32; 2];
+
+    fn new(quality: OsQuality, stage: usize) -> Self {
+        let len = quality.kernel_lengths()[stage];
+        let FirStage {
+            even_taps,
+            odd_taps,
+            kernel,
+            ..
+        } = FirStage::with_length(len);
+        let interleave = |taps: Vec<f32>| taps.iter().flat_map(|&tap| [tap, tap]).collect();
+
+        Self {
+            up_history: StereoHistory::new(even_taps.len()),
+            down_history: StereoHistory::new(len),
+            even_taps: interleave(even_taps),
+            odd_taps: interleave(odd_taps),
+            kernel: interleave(kernel),
+        }
+    }
+
+    #[inline]
+    fn upsample(&mut self, frame: [f32; 2]) -> [[f32; 2]; 2] {
+        self.up_history.push(frame);
+        let history = self.up_history.samples();
+
+        [
+            dot_stereo(&self.even_taps, history),
+            dot_stereo(&self.odd_taps, history),
+        ]
+    }
+
+    #[inline]
+    fn downsample(&mut self, [even, odd]: [[f32; 2]; 2]) -> [f32; 2] {
+        self.down_history.push(even);
+        let output = dot_stereo(&self.kernel, self.down_history.samples());
+        self.down_history.push(odd);
+
+        output
+    }
+
+    fn latency(&self) -> f64 {
+        (self.kernel.len() / 2 - 1) as f64 / 2.0
+    }
+
+    fn reset(&mut self) {
+        self.up_history.reset();
+        self.down_history.reset();
+    }
+}
+
+/// A cascade of first order allpass sections. Each section is an allpass in `z^-2` at the
+/// stage's higher rate, which is a plain first order allpass at the rate the polyphase paths run
+/// at.
+#[derive(Clone)]
+struct AllpassChain {
+    coefficients: Vec<f32>,
+    /// Every section's previous input and output.
+    state: Vec<(f32, f32)>,
+}
+
+impl AllpassChain {
+    fn new(coefficients: Vec<f32>) -> Self {
+        Self {
+            state: vec![(0.0, 0.0); coefficients.len()],
+            coefficients,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, mut sample: f32) -> f32 {
+        for (a, (prev_input, prev_output)) in self.coefficients.iter().zip(&mut self.state) {
+            let output = a * (sample - *prev_output) + *prev_input;
+            *prev_input = sample;
+            *prev_output = output;
+            sample = output;
+        }
+
+        sample
+    }
+
+    /// The chain's group delay at DC, in samples at the path's rate.
+    fn latency(&self) -> f64 {
+        self.coefficients
+            .iter()
+            .map(|&a| (1.0 - a as f64) / (1.0 + a as f64))
+            .sum()
+    }
+
+    fn reset(&mut self) {
+        self.state.fill((0.0, 0.0));
+    }
+}
+
+/// A minimum phase IIR half-band stage, built from two allpass chains where the second one's
+/// output is delayed by one sample at the higher rate. Summing the two cancels everything above
+/// a quarter of the higher rate, and splitting them up gives the polyphase components.
+#[derive(Clone)]
+pub struct IirStage {
+    /// The two paths for the interpolator and the decimator.
+    up_paths: [AllpassChain; 2],
+    down_paths: [AllpassChain; 2],
+    /// The decimator's second path's output for the previous pair of inputs, since that path
+    /// runs one sample behind.
+    down_delayed: f32,
+}
+
+impl HalfBandFilter for IirStage {
+    type Frame = f32;
+
+    fn new(quality: OsQuality, stage: usize) -> Self {
+        let coefficients =
+            half_band_allpass_coefficients(IIR_ATTENUATION_DB, quality.iir_transitions()[stage]);
+        let path = |path_idx: usize| {
+            AllpassChain::new(
+                coefficients
+                    .iter()
+                    .skip(path_idx)
+                    .step_by(2)
+                    .map(|&a| a as f32)
+                    .collect(),
+            )
+        };
+
+        Self {
+            up_paths: [path(0), path(1)],
+            down_paths: [path(0), path(1)],
+            down_delayed: 0.0,
+        }
+    }
+
+    #[inline]
+    fn upsample(&mut self, sample: f32) -> [f32; 2] {
+        let [even_path, odd_path] = &mut self.up_paths;
+
+        [even_path.process(sample), odd_path.process(sample)]
+    }
+
+    #[inline]
+    fn downsample(&mut self, [even, odd]: [f32; 2]) -> f32 {
+        let [even_path, odd_path] = &mut self.down_paths;
+        let output = 0.5 * (even_path.process(even) + self.down_delayed);
+        self.down_delayed = odd_path.process(odd);
+
+        output
+    }
+
+    /// Each direction delays DC by the average of the two paths' delays, at the higher rate.
+    fn latency(&self) -> f64 {
+        let [even_path, odd_path] = &self.up_paths;
+        even_path.latency() + odd_path.latency() + 0.5
+    }
+
+    fn reset(&mut self) {
+        for path in self.up_paths.iter_mut().chain(&mut self.down_paths) {
+            path.reset();
+        }
+        self.down_delayed = 0.0;
+    }
+}
+
+/// `IirStage` for stereo frames. The allpass sections depend on their own previous outputs, so
+/// unlike the FIR stages there's nothing to gain from interleaving the channels. Both channels
+/// share one design and are always reset together, so identical inputs produce identical outputs
+/// and the filters' phase shift can't pull the stereo image apart.
+pub struct StereoIirStage {
+    left: IirStage,
+    right: IirStage,
+}
+
+impl HalfBandFilter for StereoIirStage {
+    type Frame = [f32; 2];
+
+    fn new(quality: OsQuality, stage: usize) -> Self {
+        let left = IirStage::new(quality, stage);
+        Self {
+            right: left.clone(),
+            left,
+        }
+    }
+
+    #[inline]
+    fn upsample(&mut self, [left, right]: [f32; 2]) -> [[f32; 2]; 2] {
+        let [left_even, left_odd] = self.left.upsample(left);
+        let [right_even, right_odd] = self.right.upsample(right);
+
+        [[left_even, right_even], [left_odd, right_odd]]
+    }
+
+    #[inline]
+    fn downsample(&mut self, [even, odd]: [[f32; 2]; 2]) -> [f32; 2] {
+        [
+            self.left.downsample([even[0], odd[0]]),
+            self.right.downsample([even[1], odd[1]]),
+        ]
+    }
+
+    fn latency(&self) -> f64 {
+        self.left.latency()
+    }
+
+    fn reset(&mut self) {
+        self.left.reset();
+        self.right.reset();
+    }
+}
+
+#[inline]
+fn dot(taps: &[f32], samples: &[f32]) -> f32 {
+    taps.iter()
+        .zip(samples)
+        .map(|(tap, sample)| tap * sample)
+        .sum()
+}
+
+/// The dot products of interleaved stereo taps and samples, returned per channel. SSE is part of
+/// the x86_64 baseline, so that path doesn't need any runtime feature detection.
+#[cfg(target_arch = "x86_64")]
+#[inline]
+fn dot_stereo(taps: &[f32], samples: &[f32]) -> [f32; 2] {
+    use std::arch::x86_64::{_mm_add_ps, _mm_loadu_ps, _mm_mul_ps, _mm_setzero_ps, _mm_storeu_ps};
+
+    let len = taps.len().min(samples.len());
+    let (taps, samples) = (&taps[..len], &samples[..len]);
+    let chunks = len / 4;
+
+    // SAFETY: Every load reads four floats starting at `i * 4` with `i < len / 4`, which is in
+    //         bounds for both slices, and unaligned loads have no alignment requirements
+    let mut lanes = [0.0f32; 4];
+    unsafe {
+        let mut sum = _mm_setzero_ps();
+        for i in 0..chunks {
+            let tap = _mm_loadu_ps(taps.as_ptr().add(i * 4));
+            let sample = _mm_loadu_ps(samples.as_ptr().add(i * 4));
+            sum = _mm_add_ps(sum, _mm_mul_ps(tap, sample));
+        }
+        _mm_storeu_ps(lanes.as_mut_ptr(), sum);
+    }
+
+    // The lanes hold two frames' worth of left and right products, and an odd number of taps
+    // leaves one frame for the end
+    let mut output = [lanes[0] + lanes[2], lanes[1] + lanes[3]];
+    for (tap, sample) in taps[chunks * 4..]
+        .chunks_exact(2)
+        .zip(samples[chunks * 4..].chunks_exact(2))
+    {
+        output[0] += tap[0] * sample[0];
+        output[1] += tap[1] * sample[1];
+    }
+
+    output
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+#[inline]
+fn dot_stereo(taps: &[f32], samples: &[f32]) -> [f32; 2] {
+    dot_stereo_scalar(taps, samples)
+}
+
+/// The portable version of `dot_stereo()`. This is also compiled on x86_64 for the tests, which
+/// compare the two.
+#[cfg(any(test, not(target_arch = "x86_64")))]
+#[inline]
+fn dot_stereo_scalar(taps: &[f32], samples: &[f32]) -> [f32; 2] {
+    taps.chunks_exact(2)
+        .zip(samples.chunks_exact(2))
+        .fold([0.0; 2], |[left, right], (tap, sample)| {
+            [left + tap[0] * sample[0], right + tap[1] * sample[1]]
+        })
+}
+
+/// A Kaiser windowed sinc half-band low-pass with unity DC gain. `len` must be odd so the kernel
+/// has a center tap and a whole-sample group delay.
+fn half_band_kernel(len: usize) -> Vec<f32> {
+    assert!(len % 2 == 1);
+
+    let center = (len - 1) as f64 / 2.0;
+    let window_norm = bessel_i0(KAISER_BETA);
+    let kernel: Vec<f64> = (0..len)
+        .map(|i| {
+            let n = i as f64 - center;
+            let sinc = if n == 0.0 {
+                0.5
+            } else {
+                (PI * n / 2.0).sin() / (PI * n)
+            };
+            let ratio = n / center;
+            let window = bessel_i0(KAISER_BETA * (1.0 - ratio * ratio).sqrt()) / window_norm;
+
+            sinc * window
+        })
+        .collect();
+
+    let dc_gain: f64 = kernel.iter().sum();
+    kernel.iter().map(|tap| (tap / dc_gain) as f32).collect()
+}
+
+/// The allpass coefficients for an elliptic half-band low-pass with at least `attenuation_db` of
+/// stopband attenuation and a transition band `transition` wide, relative to the higher rate.
+/// The coefficients are sorted, and alternate between the filter's two paths. This is the
+/// classic design used by Laurent de Soras' HIIR library.
+fn half_band_allpass_coefficients(attenuation_db: f64, transition: f64) -> Vec<f64> {
+    assert!(transition > 0.0 && transition < 0.5);
+
+    let k = ((1.0 - transition * 2.0) * PI / 4.0).tan().powi(2);
+    let k_root = (1.0 - k * k).powf(0.25);
+    let e = 0.5 * (1.0 - k_root) / (1.0 + k_root);
+    let e4 = e.powi(4);
+    let q = e * (1.0 + e4 * (2.0 + e4 * (15.0 + 150.0 * e4)));
+
+    // The lowest odd order that reaches the attenuation, which needs `(order - 1) / 2` sections
+    let attenuation = 10.0f64.powf(-attenuation_db / 10.0);
+    let attenuation = attenuation / (1.0 - attenuation);
+    let order = ((attenuation * attenuation / 16.0).ln() / q.ln()).ceil() as usize;
+    let order = (order | 1).max(3);
+
+    (1..=(order - 1) / 2)
+        .map(|c| {
+            let angle = c as f64 * PI / order as f64;
+            let num: f64 = (0..)
+                .map(|i: i32| {
+                    let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                    sign * q.powi(i * (i + 1)) * ((i * 2 + 1) as f64 * angle).sin()
+                })
+                .take_while(|term| term.abs() > 1.0e-100)
+                .sum();
+            let den: f64 = (1..)
+                .map(|i: i32| {
+                    let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                    sign * q.powi(i * i) * ((i * 2) as f64 * angle).cos()
+                })
+                .take_while(|term| term.abs() > 1.0e-100)
+                .sum();
+
+            let w = num * q.powf(0.25) / (den + 0.5);
+            let w2 = w * w;
+            let x = ((1.0 - w2 * k) * (1.0 - w2 / k)).sqrt() / (1.0 + w2);
+            (1.0 - x) / (1.0 + x)
+        })
+        .collect()
+}
+
+/// The zeroth order modified Bessel function of the first kind, used for the Kaiser window.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..50 {
+        term *= half_x / k as f64;
+        sum += term * term;
+        if term * term < sum * 1.0e-12 {
+            break;
+        }
+    }
+
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interleaved stereo taps and samples with identical channels, `frames` frames long.
+    fn identical_channels(frames: usize) -> (Vec<f32>, Vec<f32>) {
+        let taps = (0..frames)
+            .flat_map(|i| [(i as f32 * 0.37).sin(); 2])
+            .collect();
+        let samples = (0..frames)
+            .flat_map(|i| [(i as f32 * 1.3).cos(); 2])
+            .collect();
+        (taps, samples)
+    }
+
+    /// The upsampled impulse response of `num_stages` stages, long enough for the minimum phase
+    /// filters to ring out.
+    fn upsampled_impulse<S: HalfBandFilter<Frame = f32>>(
+        quality: OsQuality,
+        num_stages: usize,
+    ) -> Vec<f32> {
+        let mut oversampler = Oversampler::<S>::new(quality);
+        let mut response = Vec::new();
+        for sample_idx in 0..UPSAMPLING_MEMORY_SAMPLES * 4 {
+            let impulse = if sample_idx == 0 { 1.0 } else { 0.0 };
+            oversampler.upsample(num_stages, impulse, |sample| response.push(sample));
+        }
+        response
+    }
+
+    #[test]
+    fn upsampling_gain_and_memory_hold() {
+        for quality in [OsQuality::Eco, OsQuality::Normal, OsQuality::High] {
+            for num_stages in 1..=NUM_STAGES {
+                let factor = 1 << num_stages;
+                for response in [
+                    upsampled_impulse::<FirStage>(quality, num_stages),
+                    upsampled_impulse::<IirStage>(quality, num_stages),
+                ] {
+                    let gain = (0..factor)
+                        .map(|phase| {
+                            response
+                                .iter()
+                                .skip(phase)
+                                .step_by(factor)
+                                .map(|sample| sample.abs())
+                                .sum::<f32>()
+                        })
+                        .fold(0.0, f32::max);
+                    assert!(
+                        gain < MAX_UPSAMPLING_GAIN,
+                        "{quality:?} {num_stages}: {gain}"
+                    );
+
+                    let tail: f32 = response[UPSAMPLING_MEMORY_SAMPLES * factor..]
+                        .iter()
+                        .map(|sample| sample.abs())
+                        .sum();
+                    assert!(tail < 1.0e-12, "{quality:?} {num_stages}: {tail}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dot_stereo_keeps_identical_channels_identical() {
+        // Both with and without a frame left over after the SSE version's chunks of two frames
+        for frames in [1, 2, 23, 24, 47] {
+            let (taps, samples) = identical_channels(frames);
+            for [left, right] in [
+                dot_stereo(&taps, &samples),
+                dot_stereo_scalar(&taps, &samples),
+            ] {
+                assert_eq!(left.to_bits(), right.to_bits(), "{frames} frames");
+            }
+        }
+    }
+
+    #[test]
+    fn dot_stereo_matches_the_scalar_version() {
+        for frames in [1, 2, 23, 24, 47] {
+            let (taps, mut samples) = identical_channels(frames);
+            // Different channels, so a mixup between them would show
+            for right in samples.iter_mut().skip(1).step_by(2) {
+                *right *= -0.5;
+            }
+
+            let simd = dot_stereo(&taps, &samples);
+            let scalar = dot_stereo_scalar(&taps, &samples);
+            for (simd, scalar) in simd.into_iter().zip(scalar) {
+                assert!((simd - scalar).abs() < 1.0e-5, "{frames} frames");
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Factory presets.
+//!
+//! Presets are applied through a GUI context like any other parameter change, so the host records
+//! them as automation and the smoothed parameters glide to their new values instead of jumping.
+//! Since presets are nothing more than parameter values, they're saved and restored with the
+//! rest of the plugin's state.
+
+use nih_plug::prelude::*;
+
+use crate::Oversampling;
+use crate::clip::ClipCurve;
+
+pub struct Preset {
+    pub name: &'static str,
+    /// Plain values keyed by parameter ID. Enum parameters take the variant's index and boolean
+    /// parameters zero or one. Anything that isn't listed is set back to its default.
+    values: &'static [(&'static str, f32)],
+}
+
+pub const FACTORY_PRESETS: &[Preset] = &[
+    Preset {
+        name: "Drum Smash",
+        values: &[
+            ("mode", ClipCurve::Hard as i32 as f32),
+            ("oversampling", Oversampling::X4 as i32 as f32),
+            ("drive", 9.0),
+            ("threshold_db", -6.0),
+            ("knee_db", 1.3),
+            ("output_gain", -3.0),
+        ],
+    },
+    Preset {
+        name: "Vocal Glue",
+        values: &[
+            ("mode", ClipCurve::Tanh as i32 as f32),
+            ("oversampling", Oversampling::X2 as i32 as f32),
+            ("drive", 4.0),
+            ("threshold_db", -3.0),
+            ("mix", 0.6),
+        ],
+    },
+    Preset {
+        name: "Master Ceiling",
+        values: &[
+            ("mode", ClipCurve::HardAdaa as i32 as f32),
+            ("oversampling", Oversampling::X8 as i32 as f32),
+            ("threshold_db", -0.3),
+            ("knee_db", 0.9),
+            ("lookahead_ms", 1.5),
+        ],
+    },
+    Preset {
+        name: "Wavefold FX",
+        values: &[
+            ("mode", ClipCurve::Wavefold as i32 as f32),
+            ("oversampling", Oversampling::X8 as i32 as f32),
+            ("fold_amount", 0.35),
+            ("threshold_db", -6.0),
+            ("tone_enable", 1.0),
+            ("tone_hz", 8_000.0),
+            ("mix", 0.5),
+        ],
+    },
+];
+
+/// The names of the factory presets, in the order `load_preset()` indexes them.
+pub fn preset_names() -> impl Iterator<Item = &'static str> {
+    FACTORY_PRESETS.iter().map(|preset| preset.name)
+}
+
+/// Set every parameter to the factory preset at `index`. The bypass parameter belongs to the host
+/// and the hidden morph snapshots to the user, so those are left alone. This needs to be called
+/// from the GUI thread since it goes through the host. Returns `false` if there's no preset at that
+/// index.
+pub fn load_preset(params: &impl Params, setter: &ParamSetter, index: usize) -> bool {
+    let Some(preset) = FACTORY_PRESETS.get(index) else {
+        return false;
+    };
+
+    for (id, param_ptr, _) in params.param_map() {
+        // SAFETY: The pointers from `param_map()` point into `params`, which outlives this loop
+        unsafe {
+            if param_ptr
+                .flags()
+                .intersects(ParamFlags::BYPASS | ParamFlags::HIDDEN)
+            {
+                continue;
+            }
+
+            let normalized = match preset.values.iter().find(|(preset_id, _)| *preset_id == id) {
+                Some((_, plain)) => param_ptr.preview_normalized(*plain),
+                None => param_ptr.default_normalized_value(),
+            };
+            setter.raw_context.raw_begin_set_parameter(param_ptr);
+            setter
+                .raw_context
+                .raw_set_parameter_normalized(param_ptr, normalized);
+            setter.raw_context.raw_end_set_parameter(param_ptr);
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PluginParams;
+    use crate::validate::validate_values;
+
+    #[test]
+    fn factory_presets_are_valid() {
+        let params = PluginParams::default();
+        let param_map = params.param_map();
+
+        for preset in FACTORY_PRESETS {
+            for (id, _) in preset.values {
+                assert!(
+                    param_map.iter().any(|(param_id, _, _)| param_id == id),
+                    "{}: unknown parameter {id}",
+                    preset.name
+                );
+            }
+
+            let validated = validate_values(&params, preset.values)
+                .unwrap_or_else(|id| panic!("{}: unknown parameter {id}", preset.name));
+            for value in validated {
+                assert!(!value.was_clamped(), "{}: {value:?}", preset.name);
+            }
+        }
+    }
+}
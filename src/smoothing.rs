@@ -0,0 +1,159 @@
+//! Parameter smoothing with a style and time that can change at runtime. nih-plug's smoothers are
+//! configured once when the parameters are created, so the parameters that use this are read
+//! unsmoothed and glide here instead.
+
+use nih_plug::prelude::*;
+
+#[derive(Enum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SmoothingMode {
+    /// Moves towards the target at a constant rate.
+    #[default]
+    #[name = "Linear"]
+    Linear,
+    /// Moves quickly at first and then eases into the target, which follows automation rides
+    /// more closely.
+    #[name = "Exponential"]
+    Exponential,
+}
+
+/// The smoothing style and time shared by all smoothers, derived from the parameters once per
+/// block.
+#[derive(Clone, Copy, Debug)]
+pub struct SmoothingConfig {
+    mode: SmoothingMode,
+    /// How many samples it takes to reach a new target.
+    steps: u32,
+    /// The per-sample factor the remaining distance is multiplied by in exponential mode, chosen
+    /// so that 99.99% of the distance is covered after `steps` samples.
+    coefficient: f32,
+}
+
+impl SmoothingConfig {
+    pub fn new(mode: SmoothingMode, time_ms: f32, sample_rate: f32) -> Self {
+        let steps = (time_ms / 1000.0 * sample_rate).round() as u32;
+        Self {
+            mode,
+            steps,
+            coefficient: 0.0001f32.powf((steps.max(1) as f32).recip()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParamSmoother {
+    current: f32,
+    target: f32,
+    /// The per-sample increment in linear mode.
+    step: f32,
+    steps_left: u32,
+}
+
+impl ParamSmoother {
+    /// Jump straight to `value`.
+    pub fn reset(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+        self.steps_left = 0;
+    }
+
+    /// The lowest and highest values `next()` can return while heading for `target`, for bounding
+    /// what an entire block will see.
+    pub fn range(&self, target: f32) -> (f32, f32) {
+        (self.current.min(target), self.current.max(target))
+    }
+
+    /// Advance by one sample towards `target`, starting a new glide if it changed since the last
+    /// call.
+    #[inline]
+    pub fn next(&mut self, target: f32, config: &SmoothingConfig) -> f32 {
+        if target != self.target {
+            self.target = target;
+            self.steps_left = config.steps;
+            self.step = (target - self.current) / config.steps.max(1) as f32;
+        }
+
+        if self.steps_left == 0 {
+            self.current = self.target;
+            return self.current;
+        }
+
+        self.steps_left -= 1;
+        self.current = if self.steps_left == 0 {
+            // This avoids ending up just shy of the target due to rounding errors
+            self.target
+        } else {
+            match config.mode {
+                SmoothingMode::Linear => self.current + self.step,
+                SmoothingMode::Exponential => {
+                    self.target + (self.current - self.target) * config.coefficient
+                }
+            }
+        };
+
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 ms at 48 kHz.
+    const STEPS: u32 = 48;
+
+    fn config(mode: SmoothingMode) -> SmoothingConfig {
+        SmoothingConfig::new(mode, 1.0, 48_000.0)
+    }
+
+    #[test]
+    fn reaches_the_target_in_steps_samples() {
+        for mode in [SmoothingMode::Linear, SmoothingMode::Exponential] {
+            let config = config(mode);
+            assert_eq!(config.steps, STEPS);
+
+            let mut smoother = ParamSmoother::default();
+            smoother.reset(-12.0);
+            for step in 1..STEPS {
+                let value = smoother.next(6.0, &config);
+                assert!(value > -12.0 && value < 6.0, "{mode:?} at {step}: {value}");
+            }
+            assert_eq!(smoother.next(6.0, &config), 6.0, "{mode:?}");
+            assert_eq!(smoother.next(6.0, &config), 6.0, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn linear_glide_is_linear() {
+        let config = config(SmoothingMode::Linear);
+        let mut smoother = ParamSmoother::default();
+        for step in 1..STEPS {
+            let value = smoother.next(4.8, &config);
+            let expected = 4.8 * step as f32 / STEPS as f32;
+            assert!((value - expected).abs() < 1.0e-5, "{step}: {value}");
+        }
+    }
+
+    #[test]
+    fn retargeting_starts_from_the_current_value() {
+        for mode in [SmoothingMode::Linear, SmoothingMode::Exponential] {
+            let config = config(mode);
+            let mut smoother = ParamSmoother::default();
+            let mut current = 0.0;
+            for _ in 0..STEPS / 2 {
+                current = smoother.next(1.0, &config);
+            }
+
+            let expected = match mode {
+                SmoothingMode::Linear => current + (-1.0 - current) / STEPS as f32,
+                SmoothingMode::Exponential => -1.0 + (current + 1.0) * config.coefficient,
+            };
+            let value = smoother.next(-1.0, &config);
+            assert!((value - expected).abs() < 1.0e-6, "{mode:?}: {value}");
+
+            for _ in 2..STEPS {
+                assert_ne!(smoother.next(-1.0, &config), -1.0, "{mode:?}");
+            }
+            assert_eq!(smoother.next(-1.0, &config), -1.0, "{mode:?}");
+        }
+    }
+}
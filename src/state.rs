@@ -0,0 +1,219 @@
+//! Migrations for saved plugin states.
+//!
+//! Every state stores the `STATE_VERSION` it was saved with. Loading runs the migrations for all
+//! versions after that one in order, and then fills in the defaults for any parameter the state
+//! doesn't contain. nih-plug only restores the parameters a state lists, so without that last step
+//! parameters added since the state was saved would keep whatever value they had before loading.
+
+use nih_plug::prelude::*;
+use nih_plug::wrapper::state::{ParamValue, PluginState};
+
+use crate::PluginParams;
+
+/// The key the version is persisted under, see `PluginParams::state_version`.
+pub const STATE_VERSION_KEY: &str = "state-version";
+
+/// Takes a state from the version at its index plus `FIRST_STATE_VERSION` to the next one. New
+/// migrations go at the end, for parameter changes that the defaults don't cover, like a moved ID
+/// or a changed range.
+const MIGRATIONS: &[fn(&mut PluginState)] = &[to_version_2, move_adaa_curve_back];
+
+/// The version assumed for states saved before versioning was added.
+const FIRST_STATE_VERSION: u32 = 1;
+
+/// The version states are saved with.
+pub const STATE_VERSION: u32 = FIRST_STATE_VERSION + MIGRATIONS.len() as u32;
+
+/// Bring a state saved by any version of the plugin up to `STATE_VERSION`. States from newer
+/// versions are left alone apart from the defaults, nih-plug ignores the parameters it doesn't
+/// know.
+pub fn migrate(state: &mut PluginState) {
+    let saved_version = state
+        .fields
+        .get(STATE_VERSION_KEY)
+        .and_then(|version| version.parse().ok())
+        .unwrap_or(FIRST_STATE_VERSION);
+    let first_migration = saved_version.saturating_sub(FIRST_STATE_VERSION) as usize;
+    for migration in MIGRATIONS.iter().skip(first_migration) {
+        migration(state);
+    }
+
+    fill_defaults(state);
+    // This also stamps states that didn't store a version at all
+    if saved_version <= STATE_VERSION {
+        state
+            .fields
+            .insert(STATE_VERSION_KEY.to_owned(), STATE_VERSION.to_string());
+    }
+}
+
+/// Version 1 to 2, see the migrations it runs.
+fn to_version_2(state: &mut PluginState) {
+    rename_extended_thresholds(state);
+    insert_adaa_curve(state);
+}
+
+/// Version 1 to 2: The thresholds whose range was extended up to +12 dB moved to new IDs so host
+/// automation for the old range isn't remapped. The state stores plain values, which still fit.
+fn rename_extended_thresholds(state: &mut PluginState) {
+    for (old_id, new_id) in [
+        ("threshold", "threshold_db"),
+        ("threshold_pos", "threshold_pos_db"),
+        ("threshold_neg", "threshold_neg_db"),
+        ("threshold_r", "threshold_r_db"),
+    ] {
+        if let Some(value) = state.params.remove(old_id) {
+            state.params.insert(new_id.to_owned(), value);
+        }
+    }
+}
+
+/// Version 1 to 2: The ADAA hard clip curve was inserted at index 1, which moved tanh, the only
+/// other curve at the time, to index 2.
+fn insert_adaa_curve(state: &mut PluginState) {
+    remap_curve(state, |index| if index == 1 { 2 } else { index });
+}
+
+/// Version 2 to 3: The ADAA hard clip curve moved behind tanh and cubic again, so the original
+/// curves have the indices they were saved with before version 2.
+fn move_adaa_curve_back(state: &mut PluginState) {
+    remap_curve(state, |index| match index {
+        1 => 3,
+        2 => 1,
+        3 => 2,
+        index => index,
+    });
+}
+
+/// Replace the clip curve's persisted index, which is its position in `ClipCurve`.
+fn remap_curve(state: &mut PluginState, remap: impl Fn(i32) -> i32) {
+    if let Some(ParamValue::I32(index)) = state.params.get_mut("mode") {
+        *index = remap(*index);
+    }
+}
+
+/// Add every parameter that's missing from `state` with its default value.
+fn fill_defaults(state: &mut PluginState) {
+    let defaults = PluginParams::default();
+    for (id, param, _) in defaults.param_map() {
+        if state.params.contains_key(&id) {
+            continue;
+        }
+
+        // SAFETY: The pointers point into `defaults`, which outlives this loop
+        let value = unsafe {
+            match param {
+                ParamPtr::FloatParam(p) => ParamValue::F32((*p).default_plain_value()),
+                ParamPtr::IntParam(p) => ParamValue::I32((*p).default_plain_value()),
+                ParamPtr::BoolParam(p) => ParamValue::Bool((*p).default_plain_value()),
+                ParamPtr::EnumParam(p) => ParamValue::I32((*p).default_plain_value()),
+            }
+        };
+        state.params.insert(id, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clip::ClipCurve;
+    use std::collections::BTreeMap;
+
+    /// A state as saved before versioning, with only the parameters in `params`.
+    fn unversioned_state(params: &[(&str, ParamValue)]) -> PluginState {
+        PluginState {
+            version: String::new(),
+            params: params
+                .iter()
+                .map(|(id, value)| (id.to_string(), value.clone()))
+                .collect(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn old_states_get_defaults_and_the_current_version() {
+        let mut state = unversioned_state(&[
+            ("gain", ParamValue::F32(6.0)),
+            ("oversampling", ParamValue::I32(2)),
+        ]);
+        migrate(&mut state);
+
+        // Saved values are kept, and everything added since takes its default
+        let value = |id: &str| state.params.get(id).cloned();
+        assert!(matches!(value("gain"), Some(ParamValue::F32(6.0))));
+        assert!(matches!(value("oversampling"), Some(ParamValue::I32(2))));
+        assert!(matches!(value("output_gain"), Some(ParamValue::F32(0.0))));
+        assert!(matches!(value("mix"), Some(ParamValue::F32(1.0))));
+        assert!(matches!(value("delta"), Some(ParamValue::Bool(false))));
+        assert!(matches!(value("threshold_db"), Some(ParamValue::F32(0.0))));
+        assert_eq!(
+            state.params.len(),
+            PluginParams::default().param_map().len()
+        );
+
+        assert_eq!(
+            state.fields.get(STATE_VERSION_KEY),
+            Some(&STATE_VERSION.to_string())
+        );
+    }
+
+    #[test]
+    fn original_curves_keep_their_selection() {
+        for (index, curve) in [(0, ClipCurve::Hard), (1, ClipCurve::Tanh)] {
+            let mut state = unversioned_state(&[("mode", ParamValue::I32(index))]);
+            migrate(&mut state);
+
+            let Some(&ParamValue::I32(migrated)) = state.params.get("mode") else {
+                panic!("{curve:?} is missing");
+            };
+            assert_eq!(ClipCurve::from_index(migrated as usize), curve);
+        }
+    }
+
+    #[test]
+    fn curves_saved_with_version_2_keep_their_selection() {
+        for (index, curve) in [
+            (0, ClipCurve::Hard),
+            (1, ClipCurve::HardAdaa),
+            (2, ClipCurve::Tanh),
+            (3, ClipCurve::Cubic),
+            (4, ClipCurve::Arctan),
+        ] {
+            let mut state = unversioned_state(&[("mode", ParamValue::I32(index))]);
+            state
+                .fields
+                .insert(STATE_VERSION_KEY.to_owned(), "2".to_owned());
+            migrate(&mut state);
+
+            let Some(&ParamValue::I32(migrated)) = state.params.get("mode") else {
+                panic!("{curve:?} is missing");
+            };
+            assert_eq!(ClipCurve::from_index(migrated as usize), curve);
+        }
+    }
+
+    #[test]
+    fn extended_thresholds_are_renamed() {
+        let mut state = unversioned_state(&[
+            ("threshold", ParamValue::F32(-3.0)),
+            ("threshold_pos", ParamValue::F32(-6.0)),
+            ("threshold_neg", ParamValue::F32(-9.0)),
+            ("threshold_r", ParamValue::F32(-12.0)),
+        ]);
+        migrate(&mut state);
+
+        for (old_id, new_id, expected) in [
+            ("threshold", "threshold_db", -3.0),
+            ("threshold_pos", "threshold_pos_db", -6.0),
+            ("threshold_neg", "threshold_neg_db", -9.0),
+            ("threshold_r", "threshold_r_db", -12.0),
+        ] {
+            assert!(!state.params.contains_key(old_id), "{old_id}");
+            assert!(
+                matches!(state.params.get(new_id), Some(ParamValue::F32(value)) if *value == expected),
+                "{new_id}"
+            );
+        }
+    }
+}
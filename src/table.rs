@@ -0,0 +1,193 @@
+//! The lookup table behind the table curve, and the handoff that lets other threads replace it
+//! while the audio thread is using it.
+
+use nih_plug::params::persist::PersistentField;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{PoisonError, RwLock};
+
+/// The normalized input range a table's points are spread evenly across, starting at zero. This
+/// goes past the ceiling so a table can shape the signal above it as well as below. Magnitudes
+/// past the range get the last point.
+pub const TABLE_INPUT_RANGE: f32 = 2.0;
+
+/// Identity up to the ceiling and flat from there on, which is the plain hard clipper.
+const DEFAULT_POINTS: [f32; 3] = [0.0, 1.0, 1.0];
+
+/// A transfer function for the signal's normalized magnitude, with the points linearly
+/// interpolated. The negative half mirrors the positive one like with every other curve, so the
+/// first point should be zero to keep the curve continuous through the origin.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransferTable {
+    points: Vec<f32>,
+    /// The curve's integral from zero up to every point, for `integral()`.
+    integrals: Vec<f64>,
+}
+
+impl Default for TransferTable {
+    fn default() -> Self {
+        Self::new(&DEFAULT_POINTS)
+    }
+}
+
+impl TransferTable {
+    /// Build a table from `points`, spread evenly across `TABLE_INPUT_RANGE`. Non-finite points
+    /// are replaced with zero, and anything shorter than two points falls back to the default
+    /// hard clipper.
+    pub fn new(points: &[f32]) -> Self {
+        if points.len() < 2 {
+            return Self::default();
+        }
+
+        let points: Vec<f32> = points
+            .iter()
+            .map(|&point| if point.is_finite() { point } else { 0.0 })
+            .collect();
+        let spacing = Self::spacing(points.len()) as f64;
+        let mut integral = 0.0;
+        let integrals = std::iter::once(0.0)
+            .chain(points.windows(2).map(|pair| {
+                integral += spacing * (pair[0] as f64 + pair[1] as f64) / 2.0;
+                integral
+            }))
+            .collect();
+
+        Self { points, integrals }
+    }
+
+    pub fn points(&self) -> &[f32] {
+        &self.points
+    }
+
+    /// The normalized input distance between two points.
+    fn spacing(num_points: usize) -> f32 {
+        TABLE_INPUT_RANGE / (num_points - 1) as f32
+    }
+
+    /// The normalized output for the normalized magnitude `u`.
+    #[inline(always)]
+    pub fn lookup(&self, u: f32) -> f32 {
+        let position = u.min(TABLE_INPUT_RANGE) / Self::spacing(self.points.len());
+        let index = (position as usize).min(self.points.len() - 2);
+        let fraction = position - index as f32;
+        let (start, end) = (self.points[index], self.points[index + 1]);
+        start + fraction * (end - start)
+    }
+
+    /// `lookup()` integrated from zero to `u`, in double precision. Past the table's range this
+    /// goes on as a straight line with the last point's slope.
+    #[inline(always)]
+    pub fn integral(&self, u: f64) -> f64 {
+        let last = self.points.len() - 1;
+        let spacing = Self::spacing(self.points.len()) as f64;
+        let within = u.min(TABLE_INPUT_RANGE as f64);
+        let position = within / spacing;
+        let index = (position as usize).min(last - 1);
+        let fraction = position - index as f64;
+        let (start, end) = (self.points[index] as f64, self.points[index + 1] as f64);
+        let value = start + fraction * (end - start);
+
+        self.integrals[index]
+            + fraction * spacing * (start + value) / 2.0
+            + (u - within) * self.points[last] as f64
+    }
+}
+
+/// The table curve's points, shared between the GUI, the saved state, and the audio thread.
+/// `set_points()` builds a new table on the calling thread and publishes it through an atomic
+/// pointer, and the engine swaps it in with `update()` at the start of a block. The audio thread
+/// never sees a table that's still being written, and it never allocates or frees one: the table
+/// it replaces is handed back and freed by the next `set_points()` call.
+pub struct SharedTransferTable {
+    /// The points last passed to `set_points()`. Holding the write lock also keeps concurrent
+    /// `set_points()` calls from racing each other.
+    points: RwLock<Vec<f32>>,
+    /// A table that's been published but not yet picked up by the audio thread.
+    pending: AtomicPtr<TransferTable>,
+    /// The table the audio thread replaced last, waiting to be freed.
+    retired: AtomicPtr<TransferTable>,
+}
+
+impl Default for SharedTransferTable {
+    fn default() -> Self {
+        Self {
+            points: RwLock::new(DEFAULT_POINTS.to_vec()),
+            pending: AtomicPtr::new(ptr::null_mut()),
+            retired: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+impl Drop for SharedTransferTable {
+    fn drop(&mut self) {
+        for table in [&self.pending, &self.retired] {
+            free(table.swap(ptr::null_mut(), Ordering::AcqRel));
+        }
+    }
+}
+
+impl<'a> PersistentField<'a, Vec<f32>> for SharedTransferTable {
+    fn set(&self, new_value: Vec<f32>) {
+        self.set_points(new_value);
+    }
+
+    fn map<F, R>(&self, f: F) -> R
+    where
+        F: Fn(&Vec<f32>) -> R,
+    {
+        f(&self.points.read().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl SharedTransferTable {
+    /// The points last passed to `set_points()`.
+    pub fn points(&self) -> Vec<f32> {
+        self.map(Vec::clone)
+    }
+
+    /// Replace the table, see `TransferTable::new()`. This allocates, so it shouldn't be called
+    /// from the audio thread.
+    pub fn set_points(&self, points: Vec<f32>) {
+        let mut latest = self.points.write().unwrap_or_else(PoisonError::into_inner);
+        let table = Box::into_raw(Box::new(TransferTable::new(&points)));
+        self.free_retired();
+        // A table the audio thread didn't get to yet is simply replaced
+        free(self.pending.swap(table, Ordering::AcqRel));
+        // The audio thread may have picked up the previous table in the meantime. It won't pick up
+        // this one until the table it replaced is freed here.
+        self.free_retired();
+        *latest = points;
+    }
+
+    /// Swap the pending table, if there is one, into `current`. This is the audio thread's side
+    /// and neither allocates nor frees.
+    pub fn update(&self, current: &mut Box<TransferTable>) {
+        if !self.retired.load(Ordering::Acquire).is_null() {
+            return;
+        }
+
+        let table = self.pending.swap(ptr::null_mut(), Ordering::AcqRel);
+        if table.is_null() {
+            return;
+        }
+        // SAFETY: Published tables come from `Box::into_raw()`, and swapping it out of `pending`
+        //         makes this the only owner
+        let previous = std::mem::replace(current, unsafe { Box::from_raw(table) });
+        // Only this function makes `retired` non-null, and it was null above
+        self.retired
+            .store(Box::into_raw(previous), Ordering::Release);
+    }
+
+    fn free_retired(&self) {
+        free(self.retired.swap(ptr::null_mut(), Ordering::AcqRel));
+    }
+}
+
+/// Free a table taken out of one of the atomic pointers, if there is one.
+fn free(table: *mut TransferTable) {
+    if !table.is_null() {
+        // SAFETY: The pointers only ever hold tables from `Box::into_raw()`, and the caller swapped
+        //         this one out so nothing else can reach it
+        drop(unsafe { Box::from_raw(table) });
+    }
+}
@@ -0,0 +1,96 @@
+//! Showing the gain and the thresholds in decibels or as a percentage of full scale.
+//!
+//! Only the text shown for and typed into these parameters changes with the unit. Their values,
+//! and with that saved sessions and recorded automation, are always in decibels, so switching the
+//! unit never changes what the plugin does.
+
+use nih_plug::prelude::*;
+use nih_plug::util::{db_to_gain, gain_to_db};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+type ValueToString = Arc<dyn Fn(f32) -> String + Send + Sync>;
+type StringToValue = Arc<dyn Fn(&str) -> Option<f32> + Send + Sync>;
+
+#[derive(Enum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LevelUnit {
+    #[default]
+    #[name = "dB"]
+    Decibels,
+    #[name = "%"]
+    Percent,
+}
+
+/// The unit the level parameters are currently shown in. Their formatters are fixed once the
+/// parameters are created, so they share this with the unit parameter's callback.
+#[derive(Clone, Default)]
+pub struct LevelDisplay {
+    percent: Arc<AtomicBool>,
+}
+
+impl LevelDisplay {
+    pub fn set(&self, unit: LevelUnit) {
+        self.percent
+            .store(unit == LevelUnit::Percent, Ordering::Relaxed);
+    }
+
+    /// Decibels with one decimal like the other decibel parameters, or percent with two decimals,
+    /// which is enough for every 0.1 dB step down to -24 dB to read back as the same step.
+    pub fn value_to_string(&self) -> ValueToString {
+        let percent = self.percent.clone();
+        Arc::new(move |value_db| {
+            if percent.load(Ordering::Relaxed) {
+                format!("{:.2}%", db_to_gain(value_db) * 100.0)
+            } else {
+                format!("{value_db:.1} dB")
+            }
+        })
+    }
+
+    /// Parses either unit regardless of the current one when it has a `dB` or `%` suffix. Bare
+    /// numbers are read in the current unit. With `bare_linear` set, bare numbers above 0 and up to
+    /// 1 in the decibel mode are read as a linear full scale amplitude instead, so `-6` and `6`
+    /// mean -6 and +6 dB while `0.5` means half of full scale. A bare `0` remains 0 dB rather than
+    /// silence.
+    pub fn string_to_value(&self, bare_linear: bool) -> StringToValue {
+        let percent = self.percent.clone();
+        Arc::new(move |string| parse_level(string, percent.load(Ordering::Relaxed), bare_linear))
+    }
+}
+
+fn parse_level(string: &str, percent: bool, bare_linear: bool) -> Option<f32> {
+    let string = string.trim().to_ascii_lowercase();
+    let percent_to_db = |value: f32| gain_to_db(value / 100.0);
+
+    if let Some(number) = string.strip_suffix('%') {
+        return number.trim_end().parse().ok().map(percent_to_db);
+    }
+    if let Some(number) = string.strip_suffix("db") {
+        return number.trim_end().parse().ok();
+    }
+
+    let value: f32 = string.parse().ok()?;
+    if percent {
+        Some(percent_to_db(value))
+    } else if bare_linear && value > 0.0 && value <= 1.0 {
+        Some(gain_to_db(value))
+    } else {
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_fractions_of_full_scale_are_read_as_linear() {
+        assert_eq!(parse_level("6", false, true), Some(6.0));
+        assert_eq!(parse_level("+3", false, true), Some(3.0));
+        assert_eq!(parse_level("-6", false, true), Some(-6.0));
+        assert_eq!(parse_level("0", false, true), Some(0.0));
+        assert_eq!(parse_level("1", false, true), Some(0.0));
+        assert_eq!(parse_level("0.5", false, true), Some(gain_to_db(0.5)));
+        assert_eq!(parse_level("0.5", false, false), Some(0.5));
+    }
+}
@@ -0,0 +1,93 @@
+//! Checking parameter values before applying them.
+//!
+//! The host and the GUI can only ever produce values inside a parameter's range, but values from
+//! automation scripts or hand written presets can be anything. These report what the plugin would
+//! actually end up using for them.
+
+use nih_plug::prelude::*;
+
+/// Going through the normalized value isn't exact in single precision, especially with the skewed
+/// ranges, so values that come back within this fraction of the requested value are left as they
+/// were.
+const ROUNDING_TOLERANCE: f32 = 1.0e-5;
+
+/// A plain value as requested and as it would be used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidatedValue {
+    pub id: String,
+    pub requested: f32,
+    pub value: f32,
+}
+
+impl ValidatedValue {
+    /// Whether the value had to be changed to be usable.
+    pub fn was_clamped(&self) -> bool {
+        self.value != self.requested
+    }
+}
+
+/// Validate plain values keyed by parameter ID, in the same format as the factory presets. Values
+/// are clamped to their parameter's range and snapped to its step size, enum and boolean values
+/// get rounded to the nearest index, and anything that isn't finite is replaced with the
+/// parameter's default. Returns the ID of the first unknown parameter as an error.
+pub fn validate_values(
+    params: &impl Params,
+    values: &[(&str, f32)],
+) -> Result<Vec<ValidatedValue>, String> {
+    let param_map = params.param_map();
+
+    values
+        .iter()
+        .map(|&(id, requested)| {
+            let Some((_, param_ptr, _)) = param_map.iter().find(|(param_id, _, _)| param_id == id)
+            else {
+                return Err(id.to_owned());
+            };
+
+            // SAFETY: The pointers from `param_map()` point into `params`, which outlives this
+            //         function
+            let mut value = unsafe {
+                if requested.is_finite() {
+                    param_ptr.preview_plain(param_ptr.preview_normalized(requested))
+                } else {
+                    param_ptr.preview_plain(param_ptr.default_normalized_value())
+                }
+            };
+            if requested.is_finite()
+                && (value - requested).abs() <= ROUNDING_TOLERANCE * requested.abs().max(1.0)
+            {
+                value = requested;
+            }
+
+            Ok(ValidatedValue {
+                id: id.to_owned(),
+                requested,
+                value,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PluginParams;
+
+    #[test]
+    fn thresholds_are_clamped_to_their_own_range() {
+        let params = PluginParams::default();
+        let validated = validate_values(
+            &params,
+            &[
+                ("threshold_db", 6.0),
+                ("threshold_low", 6.0),
+                ("threshold_low", -30.0),
+            ],
+        )
+        .unwrap();
+
+        assert!(!validated[0].was_clamped(), "{:?}", validated[0]);
+        assert_eq!(validated[1].value, 0.0);
+        assert_eq!(validated[2].value, -24.0);
+    }
+}
@@ -0,0 +1,80 @@
+//! Signals and measurements shared by the integration tests.
+
+#![allow(dead_code)]
+
+use rclip::engine::{ClipEngine, ClipEngineParams};
+
+pub const SAMPLE_RATE: f32 = 48_000.0;
+
+/// A sine at `frequency` with a peak amplitude of `amplitude`, `length` samples long.
+pub fn sine(frequency: f32, amplitude: f32, length: usize) -> Vec<f32> {
+    (0..length)
+        .map(|sample_idx| {
+            let phase =
+                std::f64::consts::TAU * frequency as f64 * sample_idx as f64 / SAMPLE_RATE as f64;
+            amplitude * phase.sin() as f32
+        })
+        .collect()
+}
+
+/// A linear gain from decibels, without `nih_plug`'s -100 dB floor.
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// A level in decibels, without `nih_plug`'s -100 dB floor.
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.log10()
+}
+
+pub fn rms(samples: &[f32]) -> f32 {
+    let power: f64 = samples
+        .iter()
+        .map(|&sample| sample as f64 * sample as f64)
+        .sum();
+    (power / samples.len() as f64).sqrt() as f32
+}
+
+pub fn peak(samples: &[f32]) -> f32 {
+    samples
+        .iter()
+        .fold(0.0, |peak, sample| peak.max(sample.abs()))
+}
+
+/// An engine at `SAMPLE_RATE` for `num_channels` channels.
+pub fn engine(num_channels: usize, params: ClipEngineParams) -> ClipEngine {
+    ClipEngine::new(SAMPLE_RATE, num_channels, params)
+}
+
+/// Run `channels` through `engine` in blocks of `block_size` samples and return the output.
+pub fn process<S: AsRef<[f32]>>(
+    engine: &mut ClipEngine,
+    channels: &[S],
+    block_size: usize,
+) -> Vec<Vec<f32>> {
+    let mut output: Vec<Vec<f32>> = channels
+        .iter()
+        .map(|channel| channel.as_ref().to_vec())
+        .collect();
+    let length = output.iter().map(Vec::len).min().unwrap_or(0);
+    let mut start = 0;
+    while start < length {
+        let end = (start + block_size).min(length);
+        let mut block: Vec<&mut [f32]> = output
+            .iter_mut()
+            .map(|channel| &mut channel[start..end])
+            .collect();
+        engine.process_block(&mut block);
+        start = end;
+    }
+
+    output
+}
+
+/// `signal` delayed by `delay` samples, keeping its length.
+pub fn delayed(signal: &[f32], delay: usize) -> Vec<f32> {
+    let delay = delay.min(signal.len());
+    std::iter::repeat_n(0.0, delay)
+        .chain(signal[..signal.len() - delay].iter().copied())
+        .collect()
+}
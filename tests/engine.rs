@@ -0,0 +1,649 @@
+//! End to end tests for `ClipEngine`, driven through its public interface only.
+
+mod common;
+
+use common::*;
+use rclip::engine::{ClipCurve, ClipEngineParams, ClipSide, DeltaMode, Meters, Oversampling};
+use rclip::oversample::OsFilter;
+
+#[test]
+fn zero_mix_returns_the_dry_signal() {
+    let input = sine(997.0, 1.0, 4096);
+    for oversampling in [Oversampling::Off, Oversampling::X4] {
+        let params = ClipEngineParams {
+            gain: 12.0,
+            threshold: -12.0,
+            oversampling,
+            mix: 0.0,
+            ..ClipEngineParams::default()
+        };
+        let mut engine = engine(1, params);
+        let latency = engine.latency_samples() as usize;
+
+        let output = process(&mut engine, &[&input], 512);
+        assert_eq!(output[0], delayed(&input, latency), "{oversampling:?}");
+    }
+}
+
+#[test]
+fn no_latency_without_oversampling_or_lookahead() {
+    let params = ClipEngineParams {
+        oversampling: Oversampling::Off,
+        lookahead_ms: 0.0,
+        ..ClipEngineParams::default()
+    };
+    assert_eq!(engine(2, params).latency_samples(), 0);
+
+    // 1 ms at 48 kHz
+    let lookahead = ClipEngineParams {
+        lookahead_ms: 1.0,
+        ..params
+    };
+    assert_eq!(engine(2, lookahead).latency_samples(), 48);
+}
+
+#[test]
+fn folding_curves_run_at_8x_or_more() {
+    let x8 = ClipEngineParams {
+        oversampling: Oversampling::X8,
+        ..ClipEngineParams::default()
+    };
+    let x8_latency = engine(2, x8).latency_samples();
+    for curve in [ClipCurve::SineFold, ClipCurve::Wavefold] {
+        let params = ClipEngineParams {
+            curve,
+            oversampling: Oversampling::X2,
+            ..ClipEngineParams::default()
+        };
+        assert_eq!(engine(2, params).latency_samples(), x8_latency, "{curve:?}");
+    }
+}
+
+#[test]
+fn six_channels_are_all_processed() {
+    let params = ClipEngineParams {
+        threshold: -6.0,
+        dc_block: false,
+        ..ClipEngineParams::default()
+    };
+    let ceiling = db_to_gain(-6.0);
+    let mut engine = engine(6, params);
+
+    let input: Vec<Vec<f32>> = (0..6).map(|_| sine(997.0, 1.0, 2048)).collect();
+    let output = process(&mut engine, &input, 512);
+    assert_eq!(output.len(), 6);
+    for (channel_idx, channel) in output.iter().enumerate() {
+        let channel_peak = peak(channel);
+        assert!(
+            (channel_peak - ceiling).abs() < 1.0e-6,
+            "channel {channel_idx}: {channel_peak}"
+        );
+    }
+}
+
+#[test]
+fn tone_at_max_cutoff_is_transparent() {
+    let params = ClipEngineParams {
+        dc_block: false,
+        tone_enable: true,
+        tone_hz: 20_000.0,
+        ..ClipEngineParams::default()
+    };
+
+    for frequency in [100.0, 1_000.0, 5_000.0, 10_000.0] {
+        let input = sine(frequency, db_to_gain(-12.0), 9600);
+        let output = process(&mut engine(1, params), &[&input], 512);
+
+        // Skip the filter's settling time
+        let level_db = gain_to_db(rms(&output[0][4800..])) - gain_to_db(rms(&input[4800..]));
+        assert!(level_db.abs() < 0.1, "{frequency} Hz: {level_db} dB");
+    }
+}
+
+#[test]
+fn oversampled_delta_nulls_below_the_threshold() {
+    // The half-band filters' passband ripple grows towards the top of the band and leaves about
+    // -97 dB at 1 kHz, an octave below that they null to -100 dB
+    for (frequency, limit_db) in [(997.0, -90.0), (440.0, -100.0)] {
+        // The silence in between is long enough for the engine to skip the oversampling filters,
+        // so this also covers it waking up again
+        let burst = sine(frequency, db_to_gain(-12.0), 9600);
+        let input = [burst.as_slice(), &[0.0; 9600], &burst].concat();
+
+        // The minimum phase filters' phase shift keeps them from nulling against a plain delay
+        for oversampling in [Oversampling::X2, Oversampling::X4, Oversampling::X8] {
+            let params = ClipEngineParams {
+                oversampling,
+                os_filter: OsFilter::LinearPhase,
+                delta: true,
+                dc_block: false,
+                ..ClipEngineParams::default()
+            };
+            let mut engine = engine(1, params);
+            let latency = engine.latency_samples() as usize;
+
+            let output = process(&mut engine, &[&input], 512);
+            for start in [0, 19200] {
+                // The sine starting abruptly takes the filters a moment to settle
+                let residual = &output[0][start + latency + 480..start + 9600];
+                let residual_db = gain_to_db(rms(residual)) - gain_to_db(rms(&burst));
+                assert!(
+                    residual_db < limit_db,
+                    "{oversampling:?} at {frequency} Hz from {start}: {residual_db} dB"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn three_and_four_channels_are_independent() {
+    let params = ClipEngineParams {
+        gain: 6.0,
+        threshold: -6.0,
+        oversampling: Oversampling::X2,
+        ..ClipEngineParams::default()
+    };
+    let loud = sine(997.0, 1.0, 4096);
+    let reference = process(&mut engine(1, params), &[&loud], 512);
+
+    for num_channels in [3, 4] {
+        for active_channel in 0..num_channels {
+            let input: Vec<Vec<f32>> = (0..num_channels)
+                .map(|channel_idx| {
+                    if channel_idx == active_channel {
+                        loud.clone()
+                    } else {
+                        vec![0.0; loud.len()]
+                    }
+                })
+                .collect();
+
+            let output = process(&mut engine(num_channels, params), &input, 512);
+            for (channel_idx, channel) in output.iter().enumerate() {
+                if channel_idx == active_channel {
+                    assert_eq!(channel, &reference[0], "{num_channels} channels");
+                } else {
+                    assert_eq!(peak(channel), 0.0, "{num_channels} channels");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn non_finite_input_stays_contained() {
+    let params = ClipEngineParams {
+        threshold: -6.0,
+        oversampling: Oversampling::X4,
+        lookahead_ms: 1.0,
+        sidechain_enable: true,
+        tone_enable: true,
+        tone_hz: 8_000.0,
+        ..ClipEngineParams::default()
+    };
+    let mut engine = engine(2, params);
+    let signal = sine(997.0, 1.0, 512 * 8);
+
+    for (block_idx, block) in signal.chunks(512).enumerate() {
+        let mut left = block.to_vec();
+        let mut right = block.to_vec();
+        let mut sidechain = vec![block.to_vec(), block.to_vec()];
+        if block_idx == 2 {
+            left[100] = f32::NAN;
+            right[200] = f32::INFINITY;
+            left[300] = f32::NEG_INFINITY;
+            sidechain[0][100] = f32::NAN;
+            sidechain[1][200] = f32::INFINITY;
+        }
+
+        engine.process_block_with_sidechain(&mut [&mut left, &mut right], &sidechain);
+        for channel in [&left, &right] {
+            assert!(
+                channel.iter().all(|sample| sample.is_finite()),
+                "block {block_idx}"
+            );
+        }
+        if block_idx > 2 {
+            assert!(peak(&left) > 0.1 && peak(&right) > 0.1, "block {block_idx}");
+        }
+    }
+    assert_eq!(engine.meters().non_finite_samples, 3);
+}
+
+#[test]
+fn tail_counts_down_through_silence() {
+    let params = ClipEngineParams {
+        oversampling: Oversampling::X4,
+        lookahead_ms: 1.0,
+        ..ClipEngineParams::default()
+    };
+    let mut engine = engine(1, params);
+    let latency = engine.latency_samples();
+    assert!(latency > 48);
+    assert_eq!(engine.tail_samples(), 0);
+
+    let mut burst = sine(997.0, 0.5, 512);
+    // The burst needs to end on a sample above the silence threshold
+    *burst.last_mut().unwrap() = 0.5;
+    engine.process_block(&mut [&mut burst]);
+    assert_eq!(engine.tail_samples(), latency);
+
+    for silent_samples in 1..=latency + 10 {
+        engine.process_block(&mut [&mut [0.0]]);
+        assert_eq!(
+            engine.tail_samples(),
+            latency.saturating_sub(silent_samples),
+            "after {silent_samples} silent samples"
+        );
+    }
+
+    engine.process_block(&mut [&mut [0.5]]);
+    assert_eq!(engine.tail_samples(), latency);
+}
+
+#[test]
+fn delta_modes_match_their_formulas() {
+    let (gain_db, output_gain_db, threshold_db, mix) = (6.0, -3.0, -6.0, 0.7);
+    let gain = db_to_gain(gain_db);
+    let output_gain = db_to_gain(output_gain_db);
+    let ceiling = db_to_gain(threshold_db);
+    let input = sine(997.0, 0.8, 2048);
+
+    for delta_mode in [DeltaMode::VsInput, DeltaMode::VsGained] {
+        let params = ClipEngineParams {
+            gain: gain_db,
+            output_gain: output_gain_db,
+            threshold: threshold_db,
+            mix,
+            delta: true,
+            delta_mode,
+            dc_block: false,
+            ..ClipEngineParams::default()
+        };
+        let output = process(&mut engine(1, params), &[&input], 512);
+
+        for (&dry, &delta) in input.iter().zip(&output[0]) {
+            let wet = (dry * gain).clamp(-ceiling, ceiling) * output_gain;
+            let expected = match delta_mode {
+                DeltaMode::VsInput => mix * (wet - dry),
+                DeltaMode::VsGained => mix * (wet - dry * gain * output_gain),
+            };
+            assert!(
+                (delta - expected).abs() < 1.0e-5,
+                "{delta_mode:?}: {delta} instead of {expected}"
+            );
+        }
+    }
+
+    // The gain reference is delayed along with the dry signal, so with nothing getting clipped it
+    // still cancels out the processed signal exactly while the gain ramps behind the lookahead
+    let params = ClipEngineParams {
+        output_gain: output_gain_db,
+        mix,
+        lookahead_ms: 1.0,
+        delta: true,
+        delta_mode: DeltaMode::VsGained,
+        dc_block: false,
+        ..ClipEngineParams::default()
+    };
+    let mut engine = engine(1, params);
+    assert!(engine.latency_samples() > 0);
+    let input = sine(997.0, 0.1, 4096);
+    let mut output = process(&mut engine, &[&input[..512]], 512).remove(0);
+    engine.set_params(ClipEngineParams {
+        gain: 12.0,
+        ..params
+    });
+    output.extend(process(&mut engine, &[&input[512..]], 512).remove(0));
+    assert!(peak(&output) < 1.0e-6, "{}", peak(&output));
+}
+
+#[test]
+fn delta_scales_with_the_mix() {
+    let input = sine(997.0, 1.0, 2048);
+    let delta = |mix| {
+        let params = ClipEngineParams {
+            threshold: -6.0,
+            mix,
+            delta: true,
+            dc_block: false,
+            ..ClipEngineParams::default()
+        };
+        process(&mut engine(1, params), &[&input], 512).remove(0)
+    };
+
+    // Everything the clipper takes off the sine
+    let full = delta(1.0);
+    let ceiling = db_to_gain(-6.0);
+    for (&dry, &delta) in input.iter().zip(&full) {
+        let expected = dry.clamp(-ceiling, ceiling) - dry;
+        assert!(
+            (delta - expected).abs() < 1.0e-5,
+            "{delta} instead of {expected}"
+        );
+    }
+    assert!(peak(&full) > 0.4);
+
+    assert!(delta(0.0).iter().all(|&sample| sample == 0.0));
+    for (half, full) in delta(0.5).iter().zip(&full) {
+        assert!(
+            (half - full * 0.5).abs() < 1.0e-6,
+            "{half} instead of {}",
+            full * 0.5
+        );
+    }
+}
+
+#[test]
+fn block_size_does_not_change_the_output() {
+    let params = ClipEngineParams {
+        threshold: -3.0,
+        oversampling: Oversampling::X2,
+        lookahead_ms: 1.0,
+        ..ClipEngineParams::default()
+    };
+    // The automation lands on a boundary shared by every block size, and the glide it starts
+    // crosses many of them
+    let automated = ClipEngineParams {
+        gain: 6.0,
+        threshold: -12.0,
+        ..params
+    };
+    let automation_start = 7 * 512;
+    let input = [
+        sine(997.0, 0.9, 2 * automation_start),
+        sine(433.0, 0.7, 2 * automation_start),
+    ];
+
+    let run = |block_size: usize| {
+        let mut engine = engine(2, params);
+        let (before, after): (Vec<_>, Vec<_>) = input
+            .iter()
+            .map(|channel| channel.split_at(automation_start))
+            .map(|(before, after)| (before.to_vec(), after.to_vec()))
+            .unzip();
+
+        let mut output = process(&mut engine, &before, block_size);
+        engine.set_params(automated);
+        let rest = process(&mut engine, &after, block_size);
+        for (channel, rest) in output.iter_mut().zip(rest) {
+            channel.extend(rest);
+        }
+
+        output
+    };
+
+    // Both halves in a single block each
+    let reference = run(automation_start);
+    for block_size in [1, 7, 512] {
+        assert_eq!(run(block_size), reference, "{block_size} sample blocks");
+    }
+}
+
+#[test]
+fn metered_gain_reduction_follows_the_knee() {
+    let (threshold_db, knee_db) = (-6.0, 6.0);
+    let ceiling = db_to_gain(threshold_db);
+    // Half the knee's width around the ceiling, see `knee_from_db()` and `clip_with_knee()`
+    let ratio = db_to_gain(knee_db);
+    let half_width = (ratio - 1.0) / (ratio + 1.0) * ceiling;
+    let clip = |level: f32| {
+        if level <= ceiling - half_width {
+            level
+        } else if level >= ceiling + half_width {
+            ceiling
+        } else {
+            let overshoot = level - (ceiling - half_width);
+            level - overshoot * overshoot / (4.0 * half_width)
+        }
+    };
+
+    let params = ClipEngineParams {
+        threshold: threshold_db,
+        knee: knee_db,
+        dc_block: false,
+        ..ClipEngineParams::default()
+    };
+    for step in -24..=24 {
+        // A constant level keeps the meter's decay out of the picture
+        let level = db_to_gain(threshold_db + step as f32 * 0.5);
+        let mut engine = engine(1, params);
+        process(&mut engine, &[vec![level; 256]], 256);
+
+        let expected_db = gain_to_db(level) - gain_to_db(clip(level));
+        let metered_db = engine.meters().gain_reduction_db;
+        assert!(
+            (metered_db - expected_db).abs() < 1.0e-3,
+            "{level}: {metered_db} dB metered, {expected_db} dB expected"
+        );
+    }
+}
+
+#[test]
+fn reset_meters_read_silence_as_mono() {
+    let input = sine(997.0, 0.5, 48_000);
+    let inverted: Vec<f32> = input.iter().map(|sample| -sample).collect();
+    let mut engine = engine(2, ClipEngineParams::default());
+    assert_eq!(engine.meters().correlation, 1.0);
+
+    process(&mut engine, &[&input, &inverted], 512);
+    assert!(engine.meters().correlation < -0.99);
+
+    engine.reset_meters();
+    assert_eq!(engine.meters(), Meters::silent());
+    assert_eq!(engine.meters().correlation, 1.0);
+}
+
+#[test]
+fn meters_read_before_and_after_every_gain() {
+    let input_db = -12.0;
+    // A sine's RMS sits 3 dB below its peak
+    let crest_db = gain_to_db(2.0f32.sqrt());
+    // The gain, the drive, the threshold, the output gain, and the output peak they result in.
+    // The first stays below the threshold, the second gets clipped at it.
+    for (gain, drive, threshold, output_gain, output_db) in
+        [(6.0, 3.0, 0.0, -4.0, -7.0), (9.0, 3.0, -6.0, 2.0, -4.0)]
+    {
+        let params = ClipEngineParams {
+            gain,
+            drive,
+            threshold,
+            output_gain,
+            peak_hold: true,
+            dc_block: false,
+            ..ClipEngineParams::default()
+        };
+        let mut engine = engine(1, params);
+        // Longer than the RMS meters' window
+        process(
+            &mut engine,
+            &[sine(997.0, db_to_gain(input_db), 48_000)],
+            512,
+        );
+
+        let meters = engine.meters();
+        let readings = [
+            ("input peak", meters.input_peak, input_db),
+            ("input RMS", meters.input_rms, input_db - crest_db),
+            ("output peak", meters.output_peak, output_db),
+        ];
+        for (meter, reading, expected_db) in readings {
+            let reading_db = gain_to_db(reading);
+            assert!(
+                (reading_db - expected_db).abs() < 0.01,
+                "{gain} dB gain, {meter}: {reading_db} dB read, {expected_db} dB expected"
+            );
+        }
+
+        // Clipping changes the crest factor
+        if gain + drive + input_db <= threshold {
+            let output_rms_db = gain_to_db(meters.output_rms);
+            assert!(
+                (output_rms_db - (output_db - crest_db)).abs() < 0.01,
+                "{gain} dB gain, output RMS: {output_rms_db} dB"
+            );
+        }
+    }
+}
+
+#[test]
+fn identical_channels_stay_identical_when_oversampled() {
+    let input = sine(997.0, 1.0, 4096);
+    for oversampling in [Oversampling::X2, Oversampling::X4, Oversampling::X8] {
+        for os_filter in [OsFilter::LinearPhase, OsFilter::MinimumPhase] {
+            let params = ClipEngineParams {
+                threshold: -6.0,
+                oversampling,
+                os_filter,
+                ..ClipEngineParams::default()
+            };
+            let output = process(&mut engine(2, params), &[&input, &input], 512);
+
+            let identical = output[0]
+                .iter()
+                .zip(&output[1])
+                .all(|(left, right)| left.to_bits() == right.to_bits());
+            assert!(identical, "{oversampling:?} {os_filter:?}");
+        }
+    }
+}
+
+#[test]
+fn threshold_jump_glides() {
+    let frequency = 100.0;
+    let params = ClipEngineParams {
+        threshold: 0.0,
+        dc_block: false,
+        ..ClipEngineParams::default()
+    };
+    let mut engine = engine(1, params);
+    let input = sine(frequency, 1.0, 9600);
+
+    let mut output = process(&mut engine, &[&input[..4800]], 512);
+    engine.set_params(ClipEngineParams {
+        threshold: -24.0,
+        ..params
+    });
+    output[0].extend(&process(&mut engine, &[&input[4800..]], 512)[0]);
+
+    // The sine's own steepest step, plus what the ceiling gliding down 24 dB over the smoothing
+    // time adds at most
+    let sine_step = std::f32::consts::TAU * frequency / SAMPLE_RATE;
+    let glide_step = 1.0 - db_to_gain(-24.0 * 1000.0 / (params.smoothing_ms * SAMPLE_RATE));
+    let max_step = output[0]
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .fold(0.0, f32::max);
+    assert!(
+        max_step <= sine_step + glide_step + 1.0e-6,
+        "{max_step} with a {sine_step} sine step"
+    );
+}
+
+#[test]
+fn bypass_toggles_without_clicks() {
+    let frequency = 997.0;
+    let params = ClipEngineParams {
+        threshold: -6.0,
+        ..ClipEngineParams::default()
+    };
+    let bypassed = ClipEngineParams {
+        bypass: true,
+        ..params
+    };
+    let mut engine = engine(1, params);
+    let input = sine(frequency, 1.0, 4 * 4800);
+
+    // Every toggle lands where the sine is being clipped
+    let mut output = Vec::new();
+    for (section, params) in input.chunks(4800).zip([params, bypassed, params, bypassed]) {
+        engine.set_params(params);
+        output.extend(process(&mut engine, &[section], 512).remove(0));
+    }
+
+    // The sine's own steepest step, plus what crossfading over 10 ms between it and its clipped
+    // version adds at most
+    let sine_step = std::f32::consts::TAU * frequency / SAMPLE_RATE;
+    let fade_step = (1.0 - db_to_gain(-6.0)) / (0.01 * SAMPLE_RATE);
+    let max_step = output
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .fold(0.0, f32::max);
+    assert!(
+        max_step <= sine_step + fade_step + 1.0e-3,
+        "{max_step} with a {sine_step} sine step"
+    );
+}
+
+#[test]
+fn sub_threshold_signal_passes_the_oversampling_brackets() {
+    let input = sine(997.0, db_to_gain(-6.0), 9600);
+    for oversampling in [Oversampling::X2, Oversampling::X4, Oversampling::X8] {
+        for os_filter in [OsFilter::LinearPhase, OsFilter::MinimumPhase] {
+            let params = ClipEngineParams {
+                oversampling,
+                os_filter,
+                dc_block: false,
+                ..ClipEngineParams::default()
+            };
+            let mut engine = engine(1, params);
+            let latency = engine.latency_samples() as usize;
+            let output = process(&mut engine, &[&input], 512).remove(0);
+
+            // Past the filters' onset
+            let start = latency + 480;
+            let expected = &delayed(&input, latency)[start..];
+            let output = &output[start..];
+            let level_db = gain_to_db(rms(output) / rms(expected));
+            assert!(
+                level_db.abs() < 0.01,
+                "{oversampling:?} {os_filter:?}: {level_db} dB"
+            );
+
+            // The minimum phase filters already shift the phase at 1 kHz, so only the level matches
+            if os_filter == OsFilter::LinearPhase {
+                let residual: Vec<f32> = output
+                    .iter()
+                    .zip(expected)
+                    .map(|(output, expected)| output - expected)
+                    .collect();
+                let residual_db = gain_to_db(rms(&residual) / rms(expected));
+                assert!(
+                    residual_db < -90.0,
+                    "{oversampling:?}: {residual_db} dB residual"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn untouched_polarity_is_bit_identical_with_adaa() {
+    let input = sine(997.0, 1.0, 4096);
+    for (clip_side, untouched) in [
+        (ClipSide::PositiveOnly, -1.0f32),
+        (ClipSide::NegativeOnly, 1.0f32),
+    ] {
+        let params = ClipEngineParams {
+            curve: ClipCurve::HardAdaa,
+            clip_side,
+            threshold: -6.0,
+            dc_block: false,
+            ..ClipEngineParams::default()
+        };
+        let output = process(&mut engine(1, params), &[&input], 512).remove(0);
+
+        for (&dry, &wet) in input.iter().zip(&output) {
+            if dry * untouched > 0.0 {
+                assert_eq!(wet.to_bits(), dry.to_bits(), "{clip_side:?}: {dry}");
+            }
+        }
+        // The other half still gets clipped
+        let clipped = output
+            .iter()
+            .filter(|&&wet| wet * untouched < 0.0)
+            .fold(0.0f32, |peak, wet| peak.max(wet.abs()));
+        assert!(clipped < 0.6, "{clip_side:?}: {clipped}");
+    }
+}
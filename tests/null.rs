@@ -0,0 +1,30 @@
+//! The null test mode, which proves the engine is transparent below the threshold.
+
+mod common;
+
+use common::*;
+use rclip::engine::ClipEngineParams;
+
+#[test]
+fn sub_threshold_signal_nulls() {
+    let input = sine(997.0, db_to_gain(-6.0), 48_000);
+    for lookahead_ms in [0.0, 2.0] {
+        let params = ClipEngineParams {
+            null_test: true,
+            // The null test forces these back to unity, so they can't push the sine into the
+            // threshold
+            gain: 12.0,
+            drive: 12.0,
+            lookahead_ms,
+            dc_block: false,
+            ..ClipEngineParams::default()
+        };
+        let mut engine = engine(2, params);
+
+        let output = process(&mut engine, &[&input, &input], 512);
+        for channel in output {
+            let rms_db = gain_to_db(rms(&channel));
+            assert!(rms_db < -120.0, "{lookahead_ms} ms: {rms_db} dBFS");
+        }
+    }
+}